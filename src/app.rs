@@ -1,9 +1,32 @@
+mod bench;
 mod components;
+mod cron;
+mod fallback;
+mod flaky;
+mod history;
+mod map;
+mod parallel;
+mod race;
 mod retry;
+mod schedule;
 mod supervise;
+mod timeout;
+mod wait_for;
 
+pub use bench::*;
+pub use components::{diagnose, spawn_error_status};
+pub use cron::*;
+pub use fallback::*;
+pub use flaky::*;
+pub use history::*;
+pub use map::*;
+pub use parallel::*;
+pub use race::*;
 pub use retry::*;
+pub use schedule::*;
 pub use supervise::*;
+pub use timeout::*;
+pub use wait_for::*;
 
 pub enum Transition<N, D> {
     Next(N),
@@ -0,0 +1,340 @@
+use crate::io::{Attempt, CpuTime, Exit, PipedCmdExecute};
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// Measures the CPU time of each command it runs. As this counts every child waited for in the
+/// meantime, no other command may run at the same time.
+pub struct CpuTimed<'a> {
+    inner: &'a (dyn PipedCmdExecute + Send + Sync),
+    times: Mutex<Vec<CpuTime>>,
+}
+
+impl<'a> CpuTimed<'a> {
+    pub fn new(inner: &'a (dyn PipedCmdExecute + Send + Sync)) -> Self {
+        Self {
+            inner,
+            times: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// CPU time of each run, in order, including those that could not be started.
+    pub fn times(&self) -> Vec<CpuTime> {
+        self.times.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl PipedCmdExecute for CpuTimed<'_> {
+    async fn piped_exec(&self, command: &str, attempt: usize) -> std::io::Result<Exit> {
+        let before = CpuTime::children();
+        let result = self.inner.piped_exec(command, attempt).await;
+        let cpu = CpuTime::children().since(&before);
+
+        self.times
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(cpu);
+
+        result
+    }
+}
+
+/// Wall and CPU time of the timed runs of a command, in seconds.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Benchmark {
+    pub command: String,
+    pub runs: usize,
+    /// runs exiting with anything but 0
+    pub failed: usize,
+    pub mean: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    /// mean user CPU time
+    pub user: f64,
+    /// mean system CPU time
+    pub system: f64,
+    pub times: Vec<f64>,
+}
+
+impl Benchmark {
+    /// Leaves out the first `warmup` attempts and those that could not be started. `cpu` holds
+    /// the CPU time of each attempt. None when no attempt is left.
+    pub fn from_attempts(
+        command: &str,
+        attempts: &[Attempt],
+        cpu: &[CpuTime],
+        warmup: usize,
+    ) -> Option<Self> {
+        let timed = attempts
+            .iter()
+            .zip(cpu)
+            .filter(|(attempt, _)| attempt.number > warmup && attempt.result.is_ok())
+            .collect::<Vec<_>>();
+        if timed.is_empty() {
+            return None;
+        }
+
+        let runs = timed.len();
+        let times = timed
+            .iter()
+            .map(|(attempt, _)| attempt.duration.as_secs_f64())
+            .collect::<Vec<_>>();
+        let mean = times.iter().sum::<f64>() / runs as f64;
+        let stddev = match runs {
+            1 => 0.0,
+            _ => {
+                let squares = times.iter().map(|time| (time - mean).powi(2)).sum::<f64>();
+                (squares / (runs - 1) as f64).sqrt()
+            }
+        };
+
+        let mut sorted = times.clone();
+        sorted.sort_by(f64::total_cmp);
+
+        let mean_of = |f: fn(&CpuTime) -> std::time::Duration| {
+            timed
+                .iter()
+                .map(|(_, cpu)| f(cpu).as_secs_f64())
+                .sum::<f64>()
+                / runs as f64
+        };
+
+        Some(Self {
+            command: command.to_string(),
+            runs,
+            failed: timed
+                .iter()
+                .filter(|(attempt, _)| !matches!(&attempt.result, Ok(exit) if exit.success()))
+                .count(),
+            mean,
+            stddev,
+            min: sorted[0],
+            max: sorted[runs - 1],
+            p50: percentile(&sorted, 50.0),
+            p90: percentile(&sorted, 90.0),
+            p99: percentile(&sorted, 99.0),
+            user: mean_of(|cpu| cpu.user),
+            system: mean_of(|cpu| cpu.system),
+            times,
+        })
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = format!("Benchmark '{}' ({} runs)\n", self.command, self.runs);
+
+        let _ = writeln!(
+            text,
+            "  wall  mean {:.3}s ± {:.3}s  min {:.3}s  max {:.3}s",
+            self.mean, self.stddev, self.min, self.max
+        );
+        let _ = writeln!(
+            text,
+            "        p50 {:.3}s  p90 {:.3}s  p99 {:.3}s",
+            self.p50, self.p90, self.p99
+        );
+        let _ = writeln!(
+            text,
+            "  cpu   user {:.3}s  sys {:.3}s",
+            self.user, self.system
+        );
+        if self.failed > 0 {
+            let _ = writeln!(text, "  {} of {} runs failed", self.failed, self.runs);
+        }
+
+        text
+    }
+}
+
+/// Linear interpolation between the closest ranks of the sorted samples.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+/// How many times faster the fastest benchmark ran than each of the others. Benchmarks with
+/// failed runs are left out, as failing early makes a command look fast.
+pub fn comparison(benchmarks: &[Benchmark]) -> String {
+    let benchmarks = benchmarks
+        .iter()
+        .filter(|benchmark| benchmark.failed == 0)
+        .collect::<Vec<_>>();
+    let fastest = match benchmarks.iter().min_by(|a, b| a.mean.total_cmp(&b.mean)) {
+        Some(fastest) if benchmarks.len() > 1 => *fastest,
+        _ => return String::new(),
+    };
+
+    let mut text = format!("'{}' ran\n", fastest.command);
+    for other in benchmarks.iter().filter(|b| !std::ptr::eq(**b, fastest)) {
+        let ratio = other.mean / fastest.mean;
+        let error = ratio
+            * ((fastest.stddev / fastest.mean).powi(2) + (other.stddev / other.mean).powi(2))
+                .sqrt();
+        let _ = writeln!(
+            text,
+            "  {:.2} ± {:.2} times faster than '{}'",
+            ratio, error, other.command
+        );
+    }
+
+    text
+}
+
+pub fn to_json(benchmarks: &[Benchmark]) -> String {
+    #[derive(serde::Serialize)]
+    struct Report<'a> {
+        results: &'a [Benchmark],
+    }
+
+    let mut json = serde_json::to_string(&Report {
+        results: benchmarks,
+    })
+    .unwrap_or_default();
+    json.push('\n');
+    json
+}
+
+/// One line per benchmark, without the times of the single runs.
+pub fn to_csv(benchmarks: &[Benchmark]) -> String {
+    let mut csv = "command,runs,failed,mean,stddev,min,max,p50,p90,p99,user,system\n".to_string();
+
+    for b in benchmarks {
+        let _ = writeln!(
+            csv,
+            "\"{}\",{},{},{},{},{},{},{},{},{},{},{}",
+            b.command.replace('"', "\"\""),
+            b.runs,
+            b.failed,
+            b.mean,
+            b.stddev,
+            b.min,
+            b.max,
+            b.p50,
+            b.p90,
+            b.p99,
+            b.user,
+            b.system
+        );
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn attempt(number: usize, millis: u64, result: Result<Exit, String>) -> Attempt {
+        Attempt {
+            number,
+            command: "x".into(),
+            started: chrono::Local::now(),
+            duration: Duration::from_millis(millis),
+            result,
+        }
+    }
+
+    fn cpu(attempts: &[Attempt]) -> Vec<CpuTime> {
+        attempts
+            .iter()
+            .map(|attempt| CpuTime {
+                user: attempt.duration / 2,
+                system: attempt.duration / 4,
+            })
+            .collect()
+    }
+
+    fn benchmark(command: &str, mean: f64, stddev: f64) -> Benchmark {
+        Benchmark {
+            command: command.into(),
+            runs: 2,
+            failed: 0,
+            mean,
+            stddev,
+            min: mean,
+            max: mean,
+            p50: mean,
+            p90: mean,
+            p99: mean,
+            user: 0.0,
+            system: 0.0,
+            times: vec![mean, mean],
+        }
+    }
+
+    #[test]
+    fn statistics_of_timed_runs() {
+        let attempts = [
+            attempt(1, 1000, Ok(Exit::Code(0))),
+            attempt(2, 100, Ok(Exit::Code(0))),
+            attempt(3, 400, Ok(Exit::Code(1))),
+            attempt(4, 0, Err("not found".into())),
+            attempt(5, 200, Ok(Exit::Code(0))),
+            attempt(6, 300, Ok(Exit::Code(0))),
+        ];
+
+        let b = Benchmark::from_attempts("x", &attempts, &cpu(&attempts), 1).unwrap();
+
+        assert_eq!(b.runs, 4);
+        assert_eq!(b.failed, 1);
+        assert!((b.mean - 0.25).abs() < 1e-9);
+        assert!((b.stddev - 0.129099).abs() < 1e-6);
+        assert_eq!((b.min, b.max), (0.1, 0.4));
+        assert!((b.p50 - 0.25).abs() < 1e-9);
+        assert!((b.p90 - 0.37).abs() < 1e-9);
+        assert!((b.user - 0.125).abs() < 1e-9);
+        assert!((b.system - 0.0625).abs() < 1e-9);
+        assert_eq!(b.times, [0.1, 0.4, 0.2, 0.3]);
+
+        assert!(Benchmark::from_attempts("x", &attempts[..1], &cpu(&attempts), 1).is_none());
+    }
+
+    #[test]
+    fn compare_with_fastest() {
+        let benchmarks = [benchmark("slow", 0.3, 0.03), benchmark("fast", 0.1, 0.01)];
+
+        assert_eq!(
+            comparison(&benchmarks),
+            "'fast' ran\n  3.00 ± 0.42 times faster than 'slow'\n"
+        );
+        assert_eq!(comparison(&benchmarks[..1]), "");
+    }
+
+    #[test]
+    fn leave_failures_out_of_comparison() {
+        let failing = Benchmark {
+            failed: 2,
+            ..benchmark("failing", 0.01, 0.0)
+        };
+        assert_eq!(
+            comparison(&[benchmark("slow", 0.3, 0.03), failing.clone()]),
+            ""
+        );
+
+        let benchmarks = [
+            benchmark("slow", 0.3, 0.03),
+            benchmark("fast", 0.1, 0.01),
+            failing,
+        ];
+        assert_eq!(
+            comparison(&benchmarks),
+            "'fast' ran\n  3.00 ± 0.42 times faster than 'slow'\n"
+        );
+    }
+
+    #[test]
+    fn export_as_csv() {
+        let csv = to_csv(&[benchmark("echo \"a\"", 0.5, 0.0)]);
+
+        assert_eq!(
+            csv.lines().nth(1),
+            Some("\"echo \"\"a\"\"\",2,0,0.5,0,0.5,0.5,0.5,0.5,0.5,0,0")
+        );
+    }
+}
@@ -1,9 +1,13 @@
 mod cmd_executor;
-mod cmd_not_found;
+mod cmd_killed;
+mod record;
+mod spawn_error;
 mod wait;
 
 pub use cmd_executor::*;
-pub use cmd_not_found::*;
+pub use cmd_killed::*;
+pub use record::*;
+pub use spawn_error::*;
 pub use wait::*;
 
 #[async_trait::async_trait]
@@ -3,6 +3,7 @@ use crate::io::*;
 #[derive(new)]
 pub struct CmdExecutor<'a> {
     pub command: &'a str,
+    pub attempt: usize,
     pub executor: &'a (dyn PipedCmdExecute + Send + Sync),
 }
 
@@ -11,7 +12,25 @@ impl<'a> super::Component for CmdExecutor<'a> {
     type Output = anyhow::Result<Exit>;
 
     async fn handle(&self) -> Self::Output {
-        let output = self.executor.piped_exec(self.command).await?;
+        let output = self.executor.piped_exec(self.command, self.attempt).await?;
+        Ok(output)
+    }
+}
+
+/// Runs the command once, signalling it when it runs into the timeout.
+#[derive(new)]
+pub struct TimedCmdExecutor<'a> {
+    pub command: &'a str,
+    pub timeout: &'a Timeout,
+    pub executor: &'a (dyn TimedCmdExecute + Send + Sync),
+}
+
+#[async_trait::async_trait]
+impl<'a> super::Component for TimedCmdExecutor<'a> {
+    type Output = anyhow::Result<TimedExit>;
+
+    async fn handle(&self) -> Self::Output {
+        let output = self.executor.timed_exec(self.command, self.timeout).await?;
         Ok(output)
     }
 }
@@ -0,0 +1,31 @@
+use crate::io::*;
+
+#[derive(new)]
+pub struct PrintableCmdKilled<'a, C> {
+    pub command: &'a str,
+    pub inner: C,
+    /// shown as `[name]` before the message
+    #[new(default)]
+    pub name: Option<&'a str>,
+}
+
+#[async_trait::async_trait]
+impl<'a, C: super::Component<Output = anyhow::Result<Exit>> + Send + Sync> super::Component
+    for PrintableCmdKilled<'a, C>
+{
+    type Output = anyhow::Result<Exit>;
+
+    async fn handle(&self) -> Self::Output {
+        let result = self.inner.handle().await;
+
+        if let Ok(exit @ Exit::Signal { .. }) = &result {
+            let program = self.command.split(' ').next().unwrap_or("");
+            match self.name {
+                Some(name) => eprintln!("cx: [{}] '{}' {}", name, program, exit),
+                None => eprintln!("cx: '{}' {}", program, exit),
+            }
+        }
+
+        result
+    }
+}
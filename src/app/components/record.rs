@@ -0,0 +1,104 @@
+use super::super::History;
+use crate::io::*;
+
+/// Adds the attempt made by the inner component to the history.
+#[derive(new)]
+pub struct RecordedAttempt<'a, C> {
+    pub command: &'a str,
+    pub attempt: usize,
+    pub history: &'a History,
+    pub inner: C,
+}
+
+#[async_trait::async_trait]
+impl<'a, C: super::Component<Output = anyhow::Result<Exit>> + Send + Sync> super::Component
+    for RecordedAttempt<'a, C>
+{
+    type Output = anyhow::Result<Exit>;
+
+    async fn handle(&self) -> Self::Output {
+        let started = chrono::Local::now();
+        let instant = std::time::Instant::now();
+        let result = self.inner.handle().await;
+
+        self.history.record_attempt(Attempt {
+            number: self.attempt,
+            command: self.command.to_string(),
+            started,
+            duration: instant.elapsed(),
+            result: match &result {
+                Ok(exit) => Ok(exit.clone()),
+                Err(e) => Err(super::diagnose(self.command, e)),
+            },
+        });
+
+        result
+    }
+}
+
+/// Adds the time slept by the inner component to the history.
+#[derive(new)]
+pub struct RecordedSleep<'a, C> {
+    pub history: &'a History,
+    pub inner: C,
+}
+
+#[async_trait::async_trait]
+impl<'a> super::Component for RecordedSleep<'a, super::WaitSec<'a>> {
+    type Output = ();
+
+    async fn handle(&self) -> Self::Output {
+        let instant = std::time::Instant::now();
+        self.history.start_sleep(self.inner.sec);
+        self.inner.handle().await;
+        self.history.record_sleep(instant.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Component;
+    use super::*;
+
+    struct Fixed(fn() -> anyhow::Result<Exit>);
+
+    #[async_trait::async_trait]
+    impl Component for Fixed {
+        type Output = anyhow::Result<Exit>;
+
+        async fn handle(&self) -> Self::Output {
+            (self.0)()
+        }
+    }
+
+    struct NoSleep;
+
+    #[async_trait::async_trait]
+    impl Sleep for NoSleep {
+        async fn sleep_sec(&self, _: f64) {}
+    }
+
+    #[lite_async_test::async_test]
+    async fn record_attempts_and_sleeps() {
+        let history = History::default();
+
+        let _ = RecordedAttempt::new("true", 1, &history, Fixed(|| Ok(Exit::Code(3))))
+            .handle()
+            .await;
+        let _ = RecordedAttempt::new("", 2, &history, Fixed(|| anyhow::bail!("dummy")))
+            .handle()
+            .await;
+        let wait = super::super::WaitSec {
+            sec: 0.5,
+            sleeper: &NoSleep,
+        };
+        RecordedSleep::new(&history, wait).handle().await;
+
+        let attempts = history.attempts();
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0].number, 1);
+        assert_eq!(attempts[0].result, Ok(Exit::Code(3)));
+        assert_eq!(attempts[1].number, 2);
+        assert_eq!(attempts[1].result, Err("no command entered".to_string()));
+    }
+}
@@ -0,0 +1,280 @@
+use std::path::{Path, PathBuf};
+
+#[derive(new)]
+pub struct PrintableSpawnError<'a, C> {
+    pub command: &'a str,
+    pub inner: C,
+    /// shown as `[name]` before the diagnostics, telling apart commands run at once
+    #[new(default)]
+    pub name: Option<&'a str>,
+}
+
+#[async_trait::async_trait]
+impl<'a, T: 'static, C: super::Component<Output = anyhow::Result<T>> + Send + Sync> super::Component
+    for PrintableSpawnError<'a, C>
+{
+    type Output = anyhow::Result<T>;
+
+    async fn handle(&self) -> Self::Output {
+        let result = self.inner.handle().await;
+
+        match (&result, self.name) {
+            (Err(e), Some(name)) => eprintln!("cx: [{}] {}", name, diagnose(self.command, e)),
+            (Err(e), None) => eprintln!("cx: {}", diagnose(self.command, e)),
+            (Ok(_), _) => (),
+        }
+
+        result
+    }
+}
+
+/// Exit status shells use when the command could not be started: 127 when it was not found,
+/// 126 otherwise.
+pub fn spawn_error_status(error: &anyhow::Error) -> i32 {
+    match error.downcast_ref::<std::io::Error>().map(|e| e.kind()) {
+        Some(std::io::ErrorKind::NotFound) => 127,
+        _ => 126,
+    }
+}
+
+/// Explains why the command could not be started.
+pub fn diagnose(command: &str, error: &anyhow::Error) -> String {
+    let program = command.split(' ').next().unwrap_or("");
+
+    if program.is_empty() {
+        return "no command entered".to_string();
+    }
+
+    let error = match error.downcast_ref::<std::io::Error>() {
+        Some(error) => error,
+        None => return format!("failed to execute '{}': {}", program, error),
+    };
+
+    if std::env::current_dir().is_err() {
+        return "working directory does not exist".to_string();
+    }
+
+    let path = resolve(program);
+
+    match (error.kind(), error.raw_os_error(), &path) {
+        (_, Some(ENOEXEC), _) => format!(
+            "'{}' has an unknown executable format (missing shebang?)",
+            program
+        ),
+        (_, Some(ETXTBSY), _) => format!("'{}' is busy being written", program),
+        (std::io::ErrorKind::NotFound, _, Some(path)) => match interpreter(path) {
+            Some(interpreter) => format!(
+                "'{}': interpreter {} not found",
+                program,
+                interpreter.display()
+            ),
+            None => format!("failed to execute '{}': {}", program, error),
+        },
+        (std::io::ErrorKind::NotFound, _, None) if program.contains('/') => {
+            format!("no such file '{}'", program)
+        }
+        (std::io::ErrorKind::NotFound, _, None) => match did_you_mean(program) {
+            Some(candidate) => format!(
+                "command not found '{}' (did you mean '{}'?)",
+                program, candidate
+            ),
+            None => format!("command not found '{}'", program),
+        },
+        (std::io::ErrorKind::PermissionDenied, Some(EACCES), Some(path)) if path.is_dir() => {
+            format!("'{}' is a directory", program)
+        }
+        (std::io::ErrorKind::PermissionDenied, Some(EACCES), Some(path))
+            if !is_executable(path) =>
+        {
+            format!("'{}' is not executable (chmod +x?)", program)
+        }
+        _ => format!("failed to execute '{}': {}", program, error),
+    }
+}
+
+#[cfg(unix)]
+use libc::{EACCES, ENOEXEC, ETXTBSY};
+
+#[cfg(not(unix))]
+const EACCES: i32 = -1;
+#[cfg(not(unix))]
+const ENOEXEC: i32 = -1;
+#[cfg(not(unix))]
+const ETXTBSY: i32 = -1;
+
+/// Finds the file the program refers to, searching PATH like execvp does.
+fn resolve(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return path.exists().then_some(path);
+    }
+
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(program))
+            .find(|path| path.exists())
+    })
+}
+
+/// Returns the interpreter of a script if it does not exist.
+fn interpreter(path: &Path) -> Option<PathBuf> {
+    use std::io::Read;
+
+    let mut head = [0u8; 256];
+    let len = std::fs::File::open(path).ok()?.read(&mut head).ok()?;
+    let line = head[..len]
+        .strip_prefix(b"#!")?
+        .split(|b| *b == b'\n')
+        .next()?;
+    let interpreter = String::from_utf8_lossy(line)
+        .split_whitespace()
+        .next()
+        .map(PathBuf::from)?;
+
+    (!interpreter.exists()).then_some(interpreter)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path)
+        .map(|m| m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_: &Path) -> bool {
+    true
+}
+
+/// Suggests the most similar command name found in PATH.
+fn did_you_mean(program: &str) -> Option<String> {
+    let threshold = std::cmp::max(1, program.chars().count() / 3);
+    let paths = std::env::var_os("PATH")?;
+
+    std::env::split_paths(&paths)
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .map(|name| (distance(program, &name), name))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min()
+        .map(|(_, name)| name)
+}
+
+/// Edit distance between two strings where a transposition of adjacent characters counts as
+/// a single edit (optimal string alignment).
+fn distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_error(command: &str) -> anyhow::Error {
+        std::process::Command::new(command)
+            .spawn()
+            .map(|_| ())
+            .unwrap_err()
+            .into()
+    }
+
+    #[test]
+    fn distance_between_strings() {
+        assert_eq!(distance("python", "python"), 0);
+        assert_eq!(distance("pyton", "python"), 1);
+        assert_eq!(distance("ecoh", "echo"), 1);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn diagnose_no_command() {
+        let error = anyhow::anyhow!("dummy");
+        assert_eq!(diagnose("", &error), "no command entered");
+    }
+
+    #[test]
+    fn diagnose_command_not_found() {
+        let error = spawn_error("cx-nonexistent-command-xyz");
+        assert_eq!(
+            diagnose("cx-nonexistent-command-xyz", &error),
+            "command not found 'cx-nonexistent-command-xyz'"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn diagnose_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().to_str().unwrap();
+
+        let actual = diagnose(program, &spawn_error(program));
+
+        assert_eq!(actual, format!("'{}' is a directory", program));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn diagnose_not_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("not-executable");
+        std::fs::write(&file, "#!/bin/sh\n").unwrap();
+        let program = file.to_str().unwrap();
+
+        let actual = diagnose(program, &spawn_error(program));
+
+        assert_eq!(
+            actual,
+            format!("'{}' is not executable (chmod +x?)", program)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn diagnose_missing_interpreter() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("missing-interpreter");
+        std::fs::write(&file, "#!/cx/nonexistent/python3 -u\n").unwrap();
+        std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let program = file.to_str().unwrap();
+
+        let actual = diagnose(program, &spawn_error(program));
+
+        assert_eq!(
+            actual,
+            format!(
+                "'{}': interpreter /cx/nonexistent/python3 not found",
+                program
+            )
+        );
+    }
+}
@@ -0,0 +1,285 @@
+use super::{components::*, *};
+use crate::io::*;
+use chrono::{DateTime, Utc};
+
+/// What to do about runs that were due while the previous run was still in progress or the
+/// system was suspended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Missed {
+    /// wait for the next match
+    Skip,
+    /// run once right away, however many runs were missed
+    RunOnce,
+}
+
+impl std::str::FromStr for Missed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Missed::Skip),
+            "run-once" => Ok(Missed::RunOnce),
+            _ => Err(format!("invalid missed-run policy '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    pub schedule: Schedule,
+    pub timezone: Timezone,
+    pub missed: Missed,
+}
+
+/// A run is on time when it starts within the minute it is due.
+const GRACE_SECONDS: i64 = 60;
+
+enum State {
+    Sleep,
+    ExecuteCommand,
+}
+
+/// Runs the command whenever the schedule matches. Runs never overlap: matches passing while
+/// the command is running are handled by the missed-run policy.
+pub struct CronApp<'a> {
+    command: &'a str,
+    cron: Cron,
+    count: Option<usize>,
+    attempt: usize,
+    next: Option<DateTime<Utc>>,
+    state: State,
+    executor: &'a (dyn PipedCmdExecute + Send + Sync),
+    sleeper: &'a (dyn Sleep + Send + Sync),
+    clock: &'a (dyn Clock + Send + Sync),
+    history: &'a History,
+}
+
+impl<'a> CronApp<'a> {
+    pub fn new(
+        command: &'a str,
+        cron: Cron,
+        count: Option<usize>,
+        executor: &'a (dyn PipedCmdExecute + Send + Sync),
+        sleeper: &'a (dyn Sleep + Send + Sync),
+        clock: &'a (dyn Clock + Send + Sync),
+        history: &'a History,
+    ) -> Self {
+        let next = cron.schedule.after(clock.now(), cron.timezone);
+
+        Self {
+            command,
+            cron,
+            count,
+            attempt: 0,
+            next,
+            state: State::Sleep,
+            executor,
+            sleeper,
+            clock,
+            history,
+        }
+    }
+
+    /// Number of matches from `next` up to `now` past their grace period, and whether the last
+    /// one is still on time.
+    fn due(&self, now: DateTime<Utc>) -> (usize, bool) {
+        let mut missed = 0;
+        let mut due = self.next;
+
+        while let Some(time) = due.filter(|time| *time <= now) {
+            if (now - time).num_seconds() < GRACE_SECONDS {
+                return (missed, true);
+            }
+            missed += 1;
+            due = self.cron.schedule.after(time, self.cron.timezone);
+        }
+
+        (missed, false)
+    }
+}
+
+#[async_trait::async_trait]
+impl StateMachine for CronApp<'_> {
+    type Output = anyhow::Result<()>;
+
+    async fn handle(mut self) -> Transition<Self, Self::Output> {
+        match self.state {
+            State::Sleep => {
+                let next = match self.next {
+                    Some(next) => next,
+                    None => {
+                        return Transition::Done(Err(anyhow::anyhow!(
+                            "the schedule does not match any time in the next years"
+                        )))
+                    }
+                };
+
+                let now = self.clock.now();
+                if next > now {
+                    let sec = (next - now).num_milliseconds() as f64 / 1000.0;
+                    RecordedSleep::new(
+                        self.history,
+                        WaitSec {
+                            sec,
+                            sleeper: self.sleeper,
+                        },
+                    )
+                    .handle()
+                    .await;
+                    return Transition::Next(self);
+                }
+
+                let (missed, on_time) = self.due(now);
+                self.next = self.cron.schedule.after(now, self.cron.timezone);
+
+                if missed > 0 {
+                    eprintln!(
+                        "cx: missed {} run{} of '{}'",
+                        missed,
+                        if missed == 1 { "" } else { "s" },
+                        self.command.split(' ').next().unwrap_or("")
+                    );
+                }
+
+                match (on_time, self.cron.missed) {
+                    (false, Missed::Skip) => Transition::Next(self),
+                    _ => Transition::Next(Self {
+                        state: State::ExecuteCommand,
+                        ..self
+                    }),
+                }
+            }
+            State::ExecuteCommand => {
+                self.attempt += 1;
+
+                let _ = PrintableSpawnError::new(
+                    self.command,
+                    PrintableCmdKilled::new(
+                        self.command,
+                        RecordedAttempt::new(
+                            self.command,
+                            self.attempt,
+                            self.history,
+                            CmdExecutor::new(self.command, self.attempt, self.executor),
+                        ),
+                    ),
+                )
+                .handle()
+                .await;
+
+                match self.count {
+                    Some(count) if self.attempt >= count => Transition::Done(Ok(())),
+                    _ => Transition::Next(Self {
+                        state: State::Sleep,
+                        ..self
+                    }),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Time that only moves when slept or when a command runs.
+    struct FakeTime {
+        now: Mutex<DateTime<Utc>>,
+        duration: chrono::Duration,
+        started: Mutex<Vec<String>>,
+    }
+
+    impl FakeTime {
+        fn new(now: &str, duration: chrono::Duration) -> Self {
+            Self {
+                now: Mutex::new(DateTime::parse_from_rfc3339(now).unwrap().into()),
+                duration,
+                started: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn advance(&self, duration: chrono::Duration) {
+            *self.now.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for FakeTime {
+        fn now(&self) -> DateTime<Utc> {
+            *self.now.lock().unwrap()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Sleep for FakeTime {
+        async fn sleep_sec(&self, sec: f64) {
+            self.advance(chrono::Duration::milliseconds((sec * 1000.0) as i64));
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl PipedCmdExecute for FakeTime {
+        async fn piped_exec(&self, _: &str, _: usize) -> std::io::Result<Exit> {
+            let started = self.now().format("%H:%M:%S").to_string();
+            self.started.lock().unwrap().push(started);
+            self.advance(self.duration);
+            Ok(Exit::Code(0))
+        }
+    }
+
+    async fn started(expression: &str, missed: Missed, duration: i64) -> Vec<String> {
+        let time = FakeTime::new("2024-01-01T00:04:30Z", chrono::Duration::seconds(duration));
+        let history = History::default();
+        let cron = Cron {
+            schedule: expression.parse().unwrap(),
+            timezone: Timezone::Utc,
+            missed,
+        };
+
+        let app = CronApp::new("test", cron, Some(3), &time, &time, &time, &history);
+        run(app).await.unwrap();
+
+        let started = time.started.lock().unwrap();
+        started.clone()
+    }
+
+    #[test]
+    fn parse_missed_policy() {
+        assert_eq!("skip".parse(), Ok(Missed::Skip));
+        assert_eq!("run-once".parse(), Ok(Missed::RunOnce));
+        assert!("all".parse::<Missed>().is_err());
+    }
+
+    #[tokio::test]
+    async fn run_on_each_match() {
+        let actual = started("*/5 * * * *", Missed::Skip, 10).await;
+        assert_eq!(actual, ["00:05:00", "00:10:00", "00:15:00"]);
+    }
+
+    #[tokio::test]
+    async fn skip_runs_missed_while_running() {
+        let actual = started("*/5 * * * *", Missed::Skip, 11 * 60).await;
+        assert_eq!(actual, ["00:05:00", "00:20:00", "00:35:00"]);
+    }
+
+    #[tokio::test]
+    async fn catch_up_once_on_missed_runs() {
+        let actual = started("*/5 * * * *", Missed::RunOnce, 11 * 60).await;
+        assert_eq!(actual, ["00:05:00", "00:16:00", "00:27:00"]);
+    }
+
+    #[tokio::test]
+    async fn fail_when_schedule_never_matches() {
+        let time = FakeTime::new("2024-01-01T00:00:00Z", chrono::Duration::zero());
+        let history = History::default();
+        let cron = Cron {
+            schedule: "0 0 30 2 *".parse().unwrap(),
+            timezone: Timezone::Utc,
+            missed: Missed::Skip,
+        };
+
+        let app = CronApp::new("test", cron, None, &time, &time, &time, &history);
+        assert!(run(app).await.is_err());
+    }
+}
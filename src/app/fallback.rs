@@ -0,0 +1,273 @@
+use super::*;
+use crate::io::*;
+
+/// One command of the chain with its own retry settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alternative {
+    pub command: String,
+    /// maximum number of attempts
+    pub count: usize,
+    /// retry interval (sec)
+    pub interval: f64,
+}
+
+impl Alternative {
+    /// Splits the arguments into commands at each `:::`. A command may start with `-c <count>`
+    /// and `-i <interval>` overriding the defaults, optionally followed by `--`.
+    pub fn from_args(args: &[String], count: usize, interval: f64) -> Result<Vec<Self>, String> {
+        let mut alternatives = Vec::new();
+
+        for segment in args.split(|arg| arg == ":::") {
+            let mut alternative = Alternative {
+                command: String::new(),
+                count,
+                interval,
+            };
+            let mut rest = segment;
+
+            while let [option, value, tail @ ..] = rest {
+                let invalid = || format!("invalid value '{}' for '{}'", value, option);
+
+                match option.as_str() {
+                    "-c" | "--count" => {
+                        alternative.count = value.parse().map_err(|_| invalid())?;
+                    }
+                    "-i" | "--interval" => {
+                        alternative.interval = value.parse().map_err(|_| invalid())?;
+                    }
+                    _ => break,
+                }
+                rest = tail;
+            }
+
+            if let [separator, tail @ ..] = rest {
+                if separator == "--" {
+                    rest = tail;
+                }
+            }
+
+            if !rest.is_empty() {
+                alternative.command = rest.join(" ");
+                alternatives.push(alternative);
+            }
+        }
+
+        Ok(alternatives)
+    }
+}
+
+pub enum FallbackResult {
+    /// index of the alternative that succeeded
+    Success(usize),
+    Failure,
+}
+
+type Retry<'a> =
+    RetryApp<retry::SharedParams<'a, retry::Execute<'a>>, retry::SharedParams<'a, retry::Wait<'a>>>;
+
+/// Retries each alternative in turn until one of them succeeds, stepping the `RetryApp` of the
+/// current alternative.
+pub struct FallbackApp<'a> {
+    alternatives: &'a [Alternative],
+    current: usize,
+    retry: Retry<'a>,
+    executor: &'a (dyn PipedCmdExecute + Send + Sync),
+    sleeper: &'a (dyn Sleep + Send + Sync),
+    history: &'a History,
+}
+
+impl<'a> FallbackApp<'a> {
+    /// `alternatives` must not be empty.
+    pub fn new(
+        alternatives: &'a [Alternative],
+        executor: &'a (dyn PipedCmdExecute + Send + Sync),
+        sleeper: &'a (dyn Sleep + Send + Sync),
+        history: &'a History,
+    ) -> Self {
+        Self {
+            alternatives,
+            current: 0,
+            retry: Self::retry(&alternatives[0], executor, sleeper, history),
+            executor,
+            sleeper,
+            history,
+        }
+    }
+
+    fn retry(
+        alternative: &'a Alternative,
+        executor: &'a (dyn PipedCmdExecute + Send + Sync),
+        sleeper: &'a (dyn Sleep + Send + Sync),
+        history: &'a History,
+    ) -> Retry<'a> {
+        RetryApp::new(
+            &alternative.command,
+            Some(alternative.count),
+            alternative.interval,
+            executor,
+            sleeper,
+            history,
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl StateMachine for FallbackApp<'_> {
+    type Output = FallbackResult;
+
+    async fn handle(self) -> Transition<Self, Self::Output> {
+        let alternative = &self.alternatives[self.current];
+
+        match self.retry.handle().await {
+            Transition::Next(retry) => Transition::Next(Self { retry, ..self }),
+            Transition::Done(RetryResult::Success) => {
+                eprintln!(
+                    "cx: alternative {} of {} succeeded: '{}'",
+                    self.current + 1,
+                    self.alternatives.len(),
+                    alternative.command
+                );
+                Transition::Done(FallbackResult::Success(self.current))
+            }
+            Transition::Done(RetryResult::Failure) => {
+                let next = match self.alternatives.get(self.current + 1) {
+                    Some(next) => next,
+                    None => return Transition::Done(FallbackResult::Failure),
+                };
+
+                eprintln!(
+                    "cx: '{}' failed, falling back to '{}'",
+                    alternative.command, next.command
+                );
+
+                // Numbering on keeps the attempts of all alternatives apart, in the summary as
+                // well as in the artifacts.
+                let attempts = self.history.attempts().len();
+                let retry = Self::retry(next, self.executor, self.sleeper, self.history)
+                    .starting_at(attempts + 1);
+
+                Transition::Next(Self {
+                    current: self.current + 1,
+                    retry,
+                    ..self
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Succeeds for commands starting with "ok".
+    #[derive(Default)]
+    struct TestExecutor {
+        executed: Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl PipedCmdExecute for TestExecutor {
+        async fn piped_exec(&self, command: &str, _: usize) -> std::io::Result<Exit> {
+            self.executed.lock().unwrap().push(command.to_string());
+            match command.starts_with("ok") {
+                true => Ok(Exit::Code(0)),
+                false => Ok(Exit::Code(1)),
+            }
+        }
+    }
+
+    struct NoSleep;
+
+    #[async_trait::async_trait]
+    impl Sleep for NoSleep {
+        async fn sleep_sec(&self, _: f64) {}
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_alternatives_with_own_settings() {
+        let args = args(&["curl", "a", ":::", "-c", "3", "-i", "2", "--", "curl", "b"]);
+        let actual = Alternative::from_args(&args, 1, 0.1).unwrap();
+
+        assert_eq!(
+            actual,
+            [
+                Alternative {
+                    command: "curl a".into(),
+                    count: 1,
+                    interval: 0.1,
+                },
+                Alternative {
+                    command: "curl b".into(),
+                    count: 3,
+                    interval: 2.0,
+                },
+            ]
+        );
+        assert!(Alternative::from_args(&["-c".into(), "x".into()], 1, 0.1).is_err());
+    }
+
+    #[tokio::test]
+    async fn stop_at_first_success() {
+        let executor = TestExecutor::default();
+        let history = History::default();
+        let alternatives = Alternative::from_args(
+            &args(&[
+                "ng", "a", ":::", "-c", "2", "ng", "b", ":::", "ok", ":::", "ok", "d",
+            ]),
+            1,
+            0.0,
+        )
+        .unwrap();
+
+        let app = FallbackApp::new(&alternatives, &executor, &NoSleep, &history);
+
+        assert!(matches!(run(app).await, FallbackResult::Success(2)));
+        assert_eq!(
+            *executor.executed.lock().unwrap(),
+            ["ng a", "ng b", "ng b", "ok"]
+        );
+    }
+
+    #[tokio::test]
+    async fn fail_when_every_alternative_fails() {
+        let executor = TestExecutor::default();
+        let history = History::default();
+        let alternatives = Alternative::from_args(&args(&["ng", ":::", "ng"]), 1, 0.0).unwrap();
+
+        let app = FallbackApp::new(&alternatives, &executor, &NoSleep, &history);
+
+        assert!(matches!(run(app).await, FallbackResult::Failure));
+        assert_eq!(history.attempts().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn number_attempts_across_alternatives() {
+        let executor = TestExecutor::default();
+        let history = History::default();
+        let alternatives =
+            Alternative::from_args(&args(&["-c", "2", "ng", "a", ":::", "ok"]), 1, 0.0).unwrap();
+
+        let app = FallbackApp::new(&alternatives, &executor, &NoSleep, &history);
+        run(app).await;
+
+        let attempts = history
+            .attempts()
+            .into_iter()
+            .map(|attempt| (attempt.number, attempt.command))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            attempts,
+            [
+                (1, "ng a".to_string()),
+                (2, "ng a".to_string()),
+                (3, "ok".to_string())
+            ]
+        );
+    }
+}
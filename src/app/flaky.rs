@@ -0,0 +1,300 @@
+use super::*;
+use crate::io::*;
+use std::fmt::Write;
+
+/// Normal quantile of the 95% confidence intervals.
+const Z: f64 = 1.96;
+
+/// Pass rate of the runs made so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flakiness {
+    pub runs: usize,
+    pub passed: usize,
+    /// how the runs failed and how often, most frequent first
+    pub failures: Vec<(String, usize)>,
+}
+
+impl Flakiness {
+    pub fn from_attempts(attempts: &[Attempt]) -> Self {
+        let mut failures: Vec<(String, usize)> = Vec::new();
+        let mut passed = 0;
+
+        for attempt in attempts {
+            if matches!(&attempt.result, Ok(exit) if exit.success()) {
+                passed += 1;
+                continue;
+            }
+
+            let reason = attempt.reason();
+            match failures.iter_mut().find(|(r, _)| *r == reason) {
+                Some((_, count)) => *count += 1,
+                None => failures.push((reason, 1)),
+            }
+        }
+        failures.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        Self {
+            runs: attempts.len(),
+            passed,
+            failures,
+        }
+    }
+
+    pub fn pass_rate(&self) -> f64 {
+        match self.runs {
+            0 => 0.0,
+            runs => self.passed as f64 / runs as f64,
+        }
+    }
+
+    pub fn failure_rate(&self) -> f64 {
+        1.0 - self.pass_rate()
+    }
+
+    /// 95% Wilson score interval of the pass rate.
+    pub fn interval(&self) -> (f64, f64) {
+        if self.runs == 0 {
+            return (0.0, 1.0);
+        }
+
+        let n = self.runs as f64;
+        let p = self.pass_rate();
+        let denominator = 1.0 + Z * Z / n;
+        let center = (p + Z * Z / (2.0 * n)) / denominator;
+        let half = Z * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt() / denominator;
+
+        // The bounds are exactly 0 and 1 when no run passed or all of them did, which rounding
+        // would lose.
+        let lower = if self.passed == 0 { 0.0 } else { center - half };
+        let upper = if self.passed == self.runs {
+            1.0
+        } else {
+            center + half
+        };
+
+        (lower, upper)
+    }
+
+    /// Whether the failure rate is known to be above or below the threshold.
+    fn decided(&self, threshold: f64) -> bool {
+        let (lower, upper) = self.interval();
+        1.0 - upper > threshold || 1.0 - lower < threshold
+    }
+
+    pub fn text(&self) -> String {
+        let (lower, upper) = self.interval();
+        let mut text = format!(
+            "passed {} of {} runs ({:.1}%, 95% CI {:.1}%-{:.1}%)\n",
+            self.passed,
+            self.runs,
+            self.pass_rate() * 100.0,
+            lower * 100.0,
+            upper * 100.0
+        );
+
+        for (reason, count) in &self.failures {
+            let _ = writeln!(text, "  {} x {}", count, reason);
+        }
+
+        text
+    }
+}
+
+/// How many runs to make and when to stop early.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    /// maximum number of runs
+    pub runs: usize,
+    /// number of runs before stopping early
+    pub min_runs: usize,
+    /// highest acceptable failure rate
+    pub threshold: f64,
+}
+
+type Supervise<'a> = SuperviseApp<
+    supervise::SharedParams<'a, supervise::Execute<'a>>,
+    supervise::SharedParams<'a, supervise::Wait<'a>>,
+>;
+
+/// Runs the command up to `runs` times, stepping a `SuperviseApp`, and stops early once the
+/// failure rate is known to be above or below `threshold` after at least `min_runs` runs.
+pub struct FlakyApp<'a> {
+    supervise: Supervise<'a>,
+    sampling: Sampling,
+    history: &'a History,
+}
+
+impl<'a> FlakyApp<'a> {
+    pub fn new(
+        command: &'a str,
+        sampling: Sampling,
+        interval: f64,
+        executor: &'a (dyn PipedCmdExecute + Send + Sync),
+        sleeper: &'a (dyn Sleep + Send + Sync),
+        history: &'a History,
+    ) -> Self {
+        Self {
+            supervise: SuperviseApp::new(
+                command,
+                Some(sampling.runs),
+                interval,
+                executor,
+                sleeper,
+                history,
+            ),
+            sampling,
+            history,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl StateMachine for FlakyApp<'_> {
+    type Output = Flakiness;
+
+    async fn handle(self) -> Transition<Self, Self::Output> {
+        let next = self.supervise.handle().await;
+        let flakiness = Flakiness::from_attempts(&self.history.attempts());
+        let Sampling {
+            runs,
+            min_runs,
+            threshold,
+        } = self.sampling;
+
+        match next {
+            Transition::Done(()) => Transition::Done(flakiness),
+            Transition::Next(_) if flakiness.runs >= min_runs && flakiness.decided(threshold) => {
+                eprintln!(
+                    "cx: stopping after {} of {} runs, the failure rate is known to be {} {}",
+                    flakiness.runs,
+                    runs,
+                    if flakiness.failure_rate() > threshold {
+                        "above"
+                    } else {
+                        "below"
+                    },
+                    threshold
+                );
+                Transition::Done(flakiness)
+            }
+            Transition::Next(supervise) => Transition::Next(Self { supervise, ..self }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Exits with the codes in turn, repeating the last one.
+    struct TestExecutor(Vec<i32>);
+
+    #[async_trait::async_trait]
+    impl PipedCmdExecute for TestExecutor {
+        async fn piped_exec(&self, _: &str, attempt: usize) -> std::io::Result<Exit> {
+            let index = (attempt - 1).min(self.0.len() - 1);
+            Ok(Exit::Code(self.0[index]))
+        }
+    }
+
+    struct NoSleep;
+
+    #[async_trait::async_trait]
+    impl Sleep for NoSleep {
+        async fn sleep_sec(&self, _: f64) {}
+    }
+
+    fn attempt(number: usize, result: Result<Exit, String>) -> Attempt {
+        Attempt {
+            number,
+            command: "x".into(),
+            started: chrono::Local::now(),
+            duration: Duration::ZERO,
+            result,
+        }
+    }
+
+    #[test]
+    fn pass_rate_with_interval() {
+        let attempts = [
+            attempt(1, Ok(Exit::Code(0))),
+            attempt(2, Ok(Exit::Code(1))),
+            attempt(3, Ok(Exit::Code(2))),
+            attempt(4, Ok(Exit::Code(2))),
+            attempt(5, Err("command not found 'x'".into())),
+            attempt(6, Ok(Exit::Code(0))),
+            attempt(7, Ok(Exit::Code(0))),
+            attempt(8, Ok(Exit::Code(0))),
+            attempt(9, Ok(Exit::Code(0))),
+            attempt(10, Ok(Exit::Code(0))),
+        ];
+
+        let flakiness = Flakiness::from_attempts(&attempts);
+
+        assert_eq!((flakiness.runs, flakiness.passed), (10, 6));
+        assert_eq!(
+            flakiness.failures,
+            [
+                ("exited with code 2".to_string(), 2),
+                ("exited with code 1".to_string(), 1),
+                ("command not found 'x'".to_string(), 1),
+            ]
+        );
+
+        let (lower, upper) = flakiness.interval();
+        assert!((lower - 0.3127).abs() < 1e-4);
+        assert!((upper - 0.8318).abs() < 1e-4);
+        assert!(flakiness
+            .text()
+            .starts_with("passed 6 of 10 runs (60.0%, 95% CI 31.3%-83.2%)\n"));
+    }
+
+    #[tokio::test]
+    async fn stop_once_known_to_pass() {
+        let executor = TestExecutor(vec![0]);
+        let history = History::default();
+
+        let sampling = Sampling {
+            runs: 50,
+            min_runs: 0,
+            threshold: 0.5,
+        };
+        let app = FlakyApp::new("x", sampling, 0.0, &executor, &NoSleep, &history);
+
+        assert_eq!(run(app).await.runs, 4);
+    }
+
+    #[tokio::test]
+    async fn stop_once_known_to_fail_after_min_runs() {
+        let executor = TestExecutor(vec![1, 0]);
+        let history = History::default();
+
+        let sampling = Sampling {
+            runs: 50,
+            min_runs: 5,
+            threshold: 0.0,
+        };
+        let app = FlakyApp::new("x", sampling, 0.0, &executor, &NoSleep, &history);
+        let flakiness = run(app).await;
+
+        assert_eq!((flakiness.runs, flakiness.passed), (5, 4));
+    }
+
+    #[tokio::test]
+    async fn run_all_while_undecided() {
+        let executor = TestExecutor(vec![0, 0, 0, 1, 0]);
+        let history = History::default();
+
+        let sampling = Sampling {
+            runs: 10,
+            min_runs: 0,
+            threshold: 0.1,
+        };
+        let app = FlakyApp::new("x", sampling, 0.0, &executor, &NoSleep, &history);
+        let flakiness = run(app).await;
+
+        assert_eq!((flakiness.runs, flakiness.passed), (10, 9));
+        assert_eq!(flakiness.failures, [("exited with code 1".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::io::*;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Format of the report printed at the end of a run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SummaryFormat {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for SummaryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(SummaryFormat::Text),
+            "json" => Ok(SummaryFormat::Json),
+            _ => Err(format!("invalid summary format '{}'", s)),
+        }
+    }
+}
+
+/// Attempts made and time slept so far, filled in as the state machine runs. Each record is
+/// also reported to the event log, if any.
+#[derive(Debug, Default)]
+pub struct History {
+    attempts: Mutex<Vec<Attempt>>,
+    slept: Mutex<Duration>,
+    events: Option<Arc<EventLog>>,
+    artifacts: Option<Artifacts>,
+    /// number of the latest attempt reported as started
+    started: Mutex<usize>,
+}
+
+#[derive(serde::Serialize)]
+struct Report<'a> {
+    attempts: Vec<AttemptReport<'a>>,
+    total_sleep_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_output: Option<LastOutput>,
+}
+
+impl History {
+    pub fn new(events: Option<Arc<EventLog>>) -> Self {
+        Self {
+            events,
+            ..Default::default()
+        }
+    }
+
+    /// Also writes `attempt-<n>.json` into the artifacts directory for each attempt recorded.
+    pub fn with_artifacts(self, artifacts: Option<Artifacts>) -> Self {
+        Self { artifacts, ..self }
+    }
+
+    /// `pid` is that of the child, or `None` when the command could not be spawned.
+    pub fn start_attempt(&self, number: usize, pid: Option<u32>) {
+        *self.started.lock().unwrap_or_else(|e| e.into_inner()) = number;
+
+        if let Some(events) = &self.events {
+            events.attempt_started(number, pid);
+        }
+    }
+
+    pub fn record_attempt(&self, attempt: Attempt) {
+        // An attempt that could not be spawned has not been reported as started yet.
+        let started = *self.started.lock().unwrap_or_else(|e| e.into_inner());
+        if started != attempt.number {
+            self.start_attempt(attempt.number, None);
+        }
+
+        if let Some(events) = &self.events {
+            events.attempt_exited(&attempt);
+        }
+        if let Some(artifacts) = &self.artifacts {
+            if let Err(e) = artifacts.record(&attempt) {
+                eprintln!("cx: failed to write artifacts: {}", e);
+            }
+        }
+
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        attempts.push(attempt);
+    }
+
+    pub fn start_sleep(&self, sec: f64) {
+        if let Some(events) = &self.events {
+            events.sleeping(sec);
+        }
+    }
+
+    pub fn record_sleep(&self, duration: Duration) {
+        let mut slept = self.slept.lock().unwrap_or_else(|e| e.into_inner());
+        *slept += duration;
+    }
+
+    /// Reports the end of a run that succeeded.
+    pub fn succeeded(&self) {
+        if let Some(events) = &self.events {
+            events.succeeded(self.attempts().len());
+        }
+    }
+
+    /// Reports the end of a run that failed.
+    pub fn gave_up(&self) {
+        if let Some(events) = &self.events {
+            events.gave_up(self.attempts().len());
+        }
+    }
+
+    /// Reports the end of a run that neither succeeded nor failed.
+    pub fn stopped(&self) {
+        if let Some(events) = &self.events {
+            events.stopped(self.attempts().len());
+        }
+    }
+
+    pub fn attempts(&self) -> Vec<Attempt> {
+        self.attempts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn slept(&self) -> Duration {
+        *self.slept.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn attempt(&self, number: usize) -> Option<Attempt> {
+        self.attempts().into_iter().find(|a| a.number == number)
+    }
+
+    /// The JSON summary also contains `last_output` when given.
+    pub fn summary(&self, format: SummaryFormat, last_output: Option<LastOutput>) -> String {
+        match format {
+            SummaryFormat::Text => self.text(),
+            SummaryFormat::Json => self.json(last_output),
+        }
+    }
+
+    fn text(&self) -> String {
+        use std::fmt::Write;
+
+        let mut text = format!(
+            "{:<8} {:<23} {:>10}  {}\n",
+            "attempt", "started", "duration", "result"
+        );
+
+        let attempts = self.attempts();
+        // The command is only worth showing when it changes, as between fallback alternatives.
+        let commands = attempts.iter().any(|a| a.command != attempts[0].command);
+
+        for attempt in &attempts {
+            let _ = write!(
+                text,
+                "{:<8} {:<23} {:>9.3}s  {}",
+                attempt.number,
+                attempt.started.format("%Y-%m-%d %H:%M:%S%.3f"),
+                attempt.duration.as_secs_f64(),
+                attempt.reason()
+            );
+            let _ = match commands {
+                true => writeln!(text, " ('{}')", attempt.command),
+                false => writeln!(text),
+            };
+        }
+
+        let _ = writeln!(text, "total sleep: {:.3}s", self.slept().as_secs_f64());
+        text
+    }
+
+    fn json(&self, last_output: Option<LastOutput>) -> String {
+        let attempts = self.attempts();
+        let report = Report {
+            attempts: attempts.iter().map(Attempt::report).collect(),
+            total_sleep_ms: self.slept().as_millis(),
+            last_output,
+        };
+
+        let mut json = serde_json::to_string(&report).unwrap_or_default();
+        json.push('\n');
+        json
+    }
+}
+
+/// Reports the attempts as started once their child has been spawned.
+impl SpawnObserver for History {
+    fn spawned(&self, attempt: usize, pid: Option<u32>) {
+        self.start_attempt(attempt, pid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> History {
+        let history = History::default();
+        let started = chrono::Local::now();
+
+        history.record_attempt(Attempt {
+            number: 1,
+            command: "x".into(),
+            started,
+            duration: Duration::from_millis(1500),
+            result: Ok(Exit::Code(2)),
+        });
+        history.record_attempt(Attempt {
+            number: 2,
+            command: "x".into(),
+            started,
+            duration: Duration::from_millis(20),
+            result: Err("command not found 'x'".into()),
+        });
+        history.record_sleep(Duration::from_millis(250));
+        history.record_sleep(Duration::from_millis(250));
+
+        history
+    }
+
+    #[test]
+    fn parse_summary_format() {
+        assert_eq!("text".parse(), Ok(SummaryFormat::Text));
+        assert_eq!("json".parse(), Ok(SummaryFormat::Json));
+        assert!("yaml".parse::<SummaryFormat>().is_err());
+    }
+
+    #[test]
+    fn summarize_as_text() {
+        let text = history().summary(SummaryFormat::Text, None);
+        let lines = text.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("attempt"));
+        assert!(lines[1].starts_with("1 "));
+        assert!(lines[1].ends_with("    1.500s  exited with code 2"));
+        assert!(lines[2].ends_with("    0.020s  command not found 'x'"));
+        assert_eq!(lines[3], "total sleep: 0.500s");
+    }
+
+    #[test]
+    fn summarize_as_json() {
+        let last_output = LastOutput {
+            attempt: 2,
+            stdout: vec![],
+            stderr: vec!["x: not found".into()],
+        };
+        let json = history().summary(SummaryFormat::Json, Some(last_output));
+        let report = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+
+        assert_eq!(report["attempts"][0]["attempt"], 1);
+        assert_eq!(report["attempts"][0]["duration_ms"], 1500);
+        assert_eq!(report["attempts"][0]["code"], 2);
+        assert_eq!(report["attempts"][1]["code"], serde_json::Value::Null);
+        assert_eq!(report["attempts"][1]["error"], "command not found 'x'");
+        assert_eq!(report["total_sleep_ms"], 500);
+        assert_eq!(report["last_output"]["stderr"][0], "x: not found");
+    }
+}
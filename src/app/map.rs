@@ -0,0 +1,143 @@
+use super::*;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+const SUCCEEDED: &str = "succeeded";
+const FAILED: &str = "failed";
+
+/// Tab-separated `succeeded` or `failed` and the item, one line per finished item.
+pub struct Results {
+    file: Mutex<std::fs::File>,
+}
+
+impl Results {
+    /// Starts over unless `resume` is set, in which case the items that already succeeded are
+    /// returned.
+    pub fn open(path: &Path, resume: bool) -> std::io::Result<(Self, HashSet<String>)> {
+        let succeeded = match resume {
+            true => Self::succeeded(path)?,
+            false => HashSet::new(),
+        };
+
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(resume)
+            .write(true)
+            .truncate(!resume)
+            .open(path)?;
+
+        Ok((
+            Self {
+                file: Mutex::new(file),
+            },
+            succeeded,
+        ))
+    }
+
+    /// Items whose latest result is a success. A missing file has none.
+    fn succeeded(path: &Path) -> std::io::Result<HashSet<String>> {
+        let text = match std::fs::read_to_string(path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(HashSet::new()),
+            result => result?,
+        };
+
+        let mut succeeded = HashSet::new();
+        for (status, item) in text.lines().filter_map(|line| line.split_once('\t')) {
+            match status {
+                SUCCEEDED => succeeded.insert(item.to_string()),
+                _ => succeeded.remove(item),
+            };
+        }
+
+        Ok(succeeded)
+    }
+
+    fn record(&self, item: &str, success: bool) {
+        let status = if success { SUCCEEDED } else { FAILED };
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Err(e) = writeln!(file, "{}\t{}", status, item) {
+            eprintln!("cx: failed to write results: {}", e);
+        }
+    }
+}
+
+/// The items that failed, one per line, as read by `cx map -a` to run them again.
+pub struct FailedItems {
+    file: Mutex<std::fs::File>,
+}
+
+impl FailedItems {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            file: Mutex::new(std::fs::File::create(path)?),
+        })
+    }
+
+    fn record(&self, item: &str) {
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Err(e) = writeln!(file, "{}", item) {
+            eprintln!("cx: failed to write failed items: {}", e);
+        }
+    }
+}
+
+/// Records the result of each job of the inner runner.
+#[derive(new)]
+pub struct MapJobs<R> {
+    inner: R,
+    results: Option<Results>,
+    failed: Option<FailedItems>,
+}
+
+#[async_trait::async_trait(?Send)]
+impl<R: RunJob> RunJob for MapJobs<R> {
+    async fn run_job(&self, job: &Job) -> bool {
+        let success = self.inner.run_job(job).await;
+
+        if let Some(results) = &self.results {
+            results.record(&job.name, success);
+        }
+
+        match &self.failed {
+            Some(failed) if !success => failed.record(&job.name),
+            _ => (),
+        }
+
+        success
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_from_latest_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results");
+
+        let (results, succeeded) = Results::open(&path, true).unwrap();
+        assert!(succeeded.is_empty());
+        results.record("a", true);
+        results.record("b", true);
+        results.record("c", false);
+        results.record("b", false);
+        drop(results);
+
+        let (results, succeeded) = Results::open(&path, true).unwrap();
+        assert_eq!(succeeded, HashSet::from(["a".to_string()]));
+        results.record("c", true);
+        drop(results);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.ends_with("failed\tb\nsucceeded\tc\n"));
+
+        let (_, succeeded) = Results::open(&path, false).unwrap();
+        assert!(succeeded.is_empty());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+    }
+}
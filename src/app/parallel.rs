@@ -0,0 +1,354 @@
+use super::*;
+use crate::io::*;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// When to stop running jobs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Halt {
+    /// run every job
+    Never,
+    /// stop once `count` jobs have failed, killing the running ones when `now`
+    Fail { now: bool, count: usize },
+    /// stop once `count` jobs have succeeded, killing the running ones when `now`
+    Success { now: bool, count: usize },
+}
+
+impl std::str::FromStr for Halt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid halt policy '{}'", s);
+
+        if s == "never" {
+            return Ok(Halt::Never);
+        }
+
+        let (when, condition) = s.split_once(',').ok_or_else(invalid)?;
+        let (kind, count) = condition.split_once('=').ok_or_else(invalid)?;
+
+        let now = match when {
+            "now" => true,
+            "soon" => false,
+            _ => return Err(invalid()),
+        };
+        let count = match count.parse::<usize>() {
+            Ok(count) if count > 0 => count,
+            _ => return Err(invalid()),
+        };
+
+        match kind {
+            "fail" => Ok(Halt::Fail { now, count }),
+            "success" => Ok(Halt::Success { now, count }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    /// shown in front of every line of the output
+    pub name: String,
+    pub command: String,
+}
+
+impl Job {
+    /// Splits the arguments into commands at each `:::`.
+    pub fn from_args(args: &[String]) -> Vec<Job> {
+        args.split(|arg| arg == ":::")
+            .filter(|command| !command.is_empty())
+            .map(|command| Job::new(command.join(" ")))
+            .collect()
+    }
+
+    /// One command per line, skipping blank lines.
+    pub fn from_lines(text: &str) -> Vec<Job> {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| Job::new(line.to_string()))
+            .collect()
+    }
+
+    /// Replaces each `{}` in the template with the item, or appends the item when there is none.
+    pub fn from_template(template: &str, item: &str) -> Job {
+        let command = match template.contains("{}") {
+            true => template.replace("{}", item),
+            false => format!("{} {}", template, item),
+        };
+
+        Job {
+            name: item.to_string(),
+            command,
+        }
+    }
+
+    fn new(command: String) -> Self {
+        Self {
+            name: command.clone(),
+            command,
+        }
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+pub trait RunJob {
+    /// Returns whether the job succeeded.
+    async fn run_job(&self, job: &Job) -> bool;
+}
+
+/// Runs each job as a `RetryApp`, prefixing its output with the job name.
+#[derive(new)]
+pub struct RetryJobs {
+    count: usize,
+    interval: f64,
+}
+
+#[async_trait::async_trait(?Send)]
+impl RunJob for RetryJobs {
+    async fn run_job(&self, job: &Job) -> bool {
+        let prefix = Prefix::literal(&format!("[{}] ", job.name));
+        let terminal = LineBuffered::new(Terminal::new(Some(prefix)));
+        let output = Arc::new(Output::new(vec![Box::new(terminal)]));
+        // Halting drops the jobs still running, which takes along what they have started.
+        let options = SpawnOptions {
+            #[cfg(unix)]
+            process_group: true,
+            kill_on_drop: true,
+            ..Default::default()
+        };
+        let executor = PipedCmdExecutor::new(options, output.clone());
+        let history = History::default();
+
+        let app = RetryApp::new(
+            &job.command,
+            Some(self.count),
+            self.interval,
+            &executor,
+            &Sleeper,
+            &history,
+        )
+        .with_name(&job.name);
+        let result = run(app).await;
+        output.close().await;
+
+        matches!(result, RetryResult::Success)
+    }
+}
+
+pub enum ParallelResult {
+    Success,
+    Failure,
+}
+
+/// Runs the jobs with at most `jobs` of them at once, starting the next one whenever a job
+/// finishes. Must be run within a `tokio::task::LocalSet`.
+pub struct ParallelApp<R> {
+    runner: Arc<R>,
+    pending: VecDeque<Job>,
+    running: tokio::task::JoinSet<bool>,
+    jobs: usize,
+    halt: Halt,
+    succeeded: usize,
+    failed: usize,
+    halting: bool,
+}
+
+impl<R> ParallelApp<R> {
+    pub fn new(runner: R, jobs: Vec<Job>, concurrency: usize, halt: Halt) -> Self {
+        Self {
+            runner: Arc::new(runner),
+            pending: jobs.into(),
+            running: tokio::task::JoinSet::new(),
+            jobs: std::cmp::max(concurrency, 1),
+            halt,
+            succeeded: 0,
+            failed: 0,
+            halting: false,
+        }
+    }
+
+    fn result(&self) -> ParallelResult {
+        match self.halt {
+            Halt::Success { count, .. } if self.succeeded >= count => ParallelResult::Success,
+            _ if self.failed == 0 => ParallelResult::Success,
+            _ => ParallelResult::Failure,
+        }
+    }
+
+    /// Whether the policy is met, and whether the running jobs are to be killed.
+    fn halt_reached(&self) -> Option<bool> {
+        match self.halt {
+            Halt::Fail { now, count } if self.failed >= count => Some(now),
+            Halt::Success { now, count } if self.succeeded >= count => Some(now),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: RunJob + Send + Sync + 'static> StateMachine for ParallelApp<R> {
+    type Output = ParallelResult;
+
+    async fn handle(mut self) -> Transition<Self, Self::Output> {
+        while !self.halting && self.running.len() < self.jobs {
+            let job = match self.pending.pop_front() {
+                Some(job) => job,
+                None => break,
+            };
+            let runner = self.runner.clone();
+            self.running
+                .spawn_local(async move { runner.run_job(&job).await });
+        }
+
+        match self.running.join_next().await {
+            Some(Ok(true)) => self.succeeded += 1,
+            Some(_) => self.failed += 1,
+            None => return Transition::Done(self.result()),
+        }
+
+        match self.halt_reached() {
+            Some(now) if !self.halting => {
+                self.halting = true;
+                eprintln!(
+                    "cx: halting with {} succeeded and {} failed jobs",
+                    self.succeeded, self.failed
+                );
+                if now {
+                    self.running.shutdown().await;
+                    return Transition::Done(self.result());
+                }
+            }
+            _ => (),
+        }
+
+        Transition::Next(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Succeeds for commands starting with "ok", after sleeping for the number of milliseconds
+    /// following the first space.
+    #[derive(Default)]
+    struct TestJobs {
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+        finished: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl RunJob for Arc<TestJobs> {
+        async fn run_job(&self, job: &Job) -> bool {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_running.fetch_max(running, Ordering::SeqCst);
+
+            let (result, millis) = job.command.split_once(' ').unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(millis.parse().unwrap())).await;
+
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            self.finished.lock().unwrap().push(job.command.clone());
+            result == "ok"
+        }
+    }
+
+    async fn local(app: ParallelApp<Arc<TestJobs>>) -> ParallelResult {
+        tokio::task::LocalSet::new().run_until(run(app)).await
+    }
+
+    fn jobs(commands: &[&str]) -> Vec<Job> {
+        Job::from_lines(&commands.join("\n"))
+    }
+
+    #[test]
+    fn parse_halt_policy() {
+        assert_eq!("never".parse(), Ok(Halt::Never));
+        assert_eq!(
+            "now,fail=1".parse(),
+            Ok(Halt::Fail {
+                now: true,
+                count: 1
+            })
+        );
+        assert_eq!(
+            "soon,success=2".parse(),
+            Ok(Halt::Success {
+                now: false,
+                count: 2
+            })
+        );
+        assert!("now".parse::<Halt>().is_err());
+        assert!("later,fail=1".parse::<Halt>().is_err());
+        assert!("now,fail=0".parse::<Halt>().is_err());
+    }
+
+    #[test]
+    fn split_jobs_at_separator() {
+        let args = ["echo", "a", ":::", "echo", "b", ":::"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+        let commands = Job::from_args(&args)
+            .into_iter()
+            .map(|job| job.command)
+            .collect::<Vec<_>>();
+
+        assert_eq!(commands, ["echo a", "echo b"]);
+    }
+
+    #[test]
+    fn fill_in_template() {
+        assert_eq!(
+            Job::from_template("cp {} {}.bak", "a"),
+            Job {
+                name: "a".into(),
+                command: "cp a a.bak".into(),
+            }
+        );
+        assert_eq!(Job::from_template("rm", "a").command, "rm a");
+    }
+
+    #[tokio::test]
+    async fn run_with_concurrency_cap() {
+        let runner = Arc::new(TestJobs::default());
+        let commands = ["ok 20", "ok 20", "ng 20", "ok 20", "ok 20"];
+        let app = ParallelApp::new(runner.clone(), jobs(&commands), 2, Halt::Never);
+
+        assert!(matches!(local(app).await, ParallelResult::Failure));
+        assert_eq!(runner.max_running.load(Ordering::SeqCst), 2);
+        assert_eq!(runner.finished.lock().unwrap().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn halt_now_on_failure() {
+        let runner = Arc::new(TestJobs::default());
+        let commands = ["ng 10", "ok 1000", "ok 10"];
+        let app = ParallelApp::new(
+            runner.clone(),
+            jobs(&commands),
+            2,
+            "now,fail=1".parse().unwrap(),
+        );
+
+        assert!(matches!(local(app).await, ParallelResult::Failure));
+        assert_eq!(*runner.finished.lock().unwrap(), ["ng 10"]);
+    }
+
+    #[tokio::test]
+    async fn halt_soon_on_success() {
+        let runner = Arc::new(TestJobs::default());
+        let commands = ["ok 10", "ng 50", "ok 10"];
+        let app = ParallelApp::new(
+            runner.clone(),
+            jobs(&commands),
+            2,
+            "soon,success=1".parse().unwrap(),
+        );
+
+        assert!(matches!(local(app).await, ParallelResult::Success));
+        assert_eq!(*runner.finished.lock().unwrap(), ["ok 10", "ng 50"]);
+    }
+}
@@ -0,0 +1,234 @@
+use super::{components::*, *};
+use crate::io::*;
+use std::sync::Arc;
+
+#[async_trait::async_trait(?Send)]
+pub trait Compete {
+    /// Runs the alternative at `index` to its end. Dropping the future kills it.
+    async fn compete(&self, index: usize, job: &Job) -> anyhow::Result<Exit>;
+
+    /// Shows the output of the alternative whose result is taken.
+    async fn finish(&self, index: usize);
+}
+
+/// Runs each alternative in a process group of its own, holding back its output until it is
+/// known to be the one taken unless `all_output` is set.
+pub struct RaceJobs {
+    alternatives: Vec<(Arc<Output>, PipedCmdExecutor)>,
+    all_output: bool,
+}
+
+impl RaceJobs {
+    pub fn new(jobs: &[Job], all_output: bool) -> Self {
+        let alternatives = jobs
+            .iter()
+            .map(|job| {
+                let terminal = match all_output {
+                    true => {
+                        let prefix = Prefix::literal(&format!("[{}] ", job.name));
+                        Box::new(LineBuffered::new(Terminal::new(Some(prefix))))
+                            as Box<dyn OutputSink>
+                    }
+                    false => Box::new(Captured::new(Terminal::new(None), OutputMode::Final)),
+                };
+                let output = Arc::new(Output::new(vec![terminal]));
+
+                let options = SpawnOptions {
+                    #[cfg(unix)]
+                    process_group: true,
+                    kill_on_drop: true,
+                    ..Default::default()
+                };
+
+                (output.clone(), PipedCmdExecutor::new(options, output))
+            })
+            .collect();
+
+        Self {
+            alternatives,
+            all_output,
+        }
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl Compete for RaceJobs {
+    async fn compete(&self, index: usize, job: &Job) -> anyhow::Result<Exit> {
+        let (_, executor) = &self.alternatives[index];
+        let result = CmdExecutor::new(&job.command, 1, executor).handle().await;
+
+        if let Err(e) = &result {
+            eprintln!("cx: [{}] {}", job.name, diagnose(&job.command, e));
+        }
+
+        result
+    }
+
+    async fn finish(&self, index: usize) {
+        if self.all_output {
+            for (output, _) in &self.alternatives {
+                output.close().await;
+            }
+        } else {
+            self.alternatives[index].0.close().await;
+        }
+    }
+}
+
+pub enum RaceResult {
+    Won,
+    /// every alternative failed, with the exit status of the best failure
+    Lost(i32),
+}
+
+/// How good a failure is: an exit code over a signal over a failure to start.
+fn rank(result: &anyhow::Result<Exit>) -> u8 {
+    match result {
+        Ok(Exit::Code(_)) => 0,
+        Ok(Exit::Signal { .. }) => 1,
+        Err(_) => 2,
+    }
+}
+
+/// Runs every alternative at once, taking the first one to succeed and killing the rest. Must be
+/// run within a `tokio::task::LocalSet`.
+pub struct RaceApp<R> {
+    runner: Arc<R>,
+    jobs: Vec<Job>,
+    running: tokio::task::JoinSet<(usize, anyhow::Result<Exit>)>,
+    started: bool,
+    /// rank, index and exit status of the best failure so far
+    best: Option<(u8, usize, i32)>,
+}
+
+impl<R: Compete + 'static> RaceApp<R> {
+    /// Runs `Compete::finish` on the `LocalSet`, as its future is not `Send`.
+    async fn finish(&self, index: usize) {
+        let runner = self.runner.clone();
+        let _ = tokio::task::spawn_local(async move { runner.finish(index).await }).await;
+    }
+}
+
+impl<R> RaceApp<R> {
+    pub fn new(runner: R, jobs: Vec<Job>) -> Self {
+        Self {
+            runner: Arc::new(runner),
+            jobs,
+            running: tokio::task::JoinSet::new(),
+            started: false,
+            best: None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<R: Compete + Send + Sync + 'static> StateMachine for RaceApp<R> {
+    type Output = RaceResult;
+
+    async fn handle(mut self) -> Transition<Self, Self::Output> {
+        if !self.started {
+            self.started = true;
+
+            for (index, job) in self.jobs.iter().cloned().enumerate() {
+                let runner = self.runner.clone();
+                self.running
+                    .spawn_local(async move { (index, runner.compete(index, &job).await) });
+            }
+        }
+
+        let (index, result) = match self.running.join_next().await {
+            Some(Ok(finished)) => finished,
+            Some(Err(_)) => return Transition::Next(self),
+            None => {
+                return Transition::Done(match self.best {
+                    Some((_, index, status)) => {
+                        self.finish(index).await;
+                        RaceResult::Lost(status)
+                    }
+                    None => RaceResult::Lost(1),
+                })
+            }
+        };
+
+        if let Ok(exit) = &result {
+            if exit.success() {
+                if !self.running.is_empty() {
+                    eprintln!(
+                        "cx: '{}' won, killing {} other alternative{}",
+                        self.jobs[index].name,
+                        self.running.len(),
+                        if self.running.len() == 1 { "" } else { "s" }
+                    );
+                }
+                self.running.shutdown().await;
+                self.finish(index).await;
+                return Transition::Done(RaceResult::Won);
+            }
+        }
+
+        let status = match &result {
+            Ok(exit) => exit.status(),
+            Err(e) => spawn_error_status(e),
+        };
+        let candidate = (rank(&result), index, status);
+        self.best = Some(self.best.map_or(candidate, |best| best.min(candidate)));
+
+        Transition::Next(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Exits with the code given first in the command after sleeping for the milliseconds given
+    /// second, or fails to start for a negative code.
+    #[derive(Default)]
+    struct TestRace {
+        finished: Mutex<Vec<String>>,
+        shown: Mutex<Option<usize>>,
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl Compete for Arc<TestRace> {
+        async fn compete(&self, _: usize, job: &Job) -> anyhow::Result<Exit> {
+            let (code, millis) = job.command.split_once(' ').unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(millis.parse().unwrap())).await;
+
+            self.finished.lock().unwrap().push(job.command.clone());
+            match code.parse().unwrap() {
+                code if code < 0 => anyhow::bail!("failed to start"),
+                code => Ok(Exit::Code(code)),
+            }
+        }
+
+        async fn finish(&self, index: usize) {
+            *self.shown.lock().unwrap() = Some(index);
+        }
+    }
+
+    async fn race(runner: &Arc<TestRace>, commands: &[&str]) -> RaceResult {
+        let app = RaceApp::new(runner.clone(), Job::from_lines(&commands.join("\n")));
+        tokio::task::LocalSet::new().run_until(run(app)).await
+    }
+
+    #[tokio::test]
+    async fn first_success_wins() {
+        let runner = Arc::new(TestRace::default());
+        let result = race(&runner, &["1 10", "0 30", "0 1000"]).await;
+
+        assert!(matches!(result, RaceResult::Won));
+        assert_eq!(*runner.finished.lock().unwrap(), ["1 10", "0 30"]);
+        assert_eq!(*runner.shown.lock().unwrap(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn best_failure_when_all_fail() {
+        let runner = Arc::new(TestRace::default());
+        let result = race(&runner, &["-1 10", "3 30", "2 20"]).await;
+
+        assert!(matches!(result, RaceResult::Lost(3)));
+        assert_eq!(*runner.shown.lock().unwrap(), Some(1));
+    }
+}
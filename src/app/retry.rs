@@ -29,9 +29,12 @@ where
             State::ExecuteCommand(component) => match self.count {
                 Some(0) => Transition::Done(RetryResult::Failure),
                 _ => match (component.handle().await, self.count) {
-                    (anyhow::Result::Ok(exit), _) if *exit.code() == 0 => {
+                    (anyhow::Result::Ok(exit), _) if exit.success() => {
                         Transition::Done(RetryResult::Success)
                     }
+                    (anyhow::Result::Ok(exit), _) if exit.limit_exceeded() => {
+                        Transition::Done(RetryResult::Failure)
+                    }
                     (_, Some(1)) => Transition::Done(RetryResult::Failure),
                     (_, _) => Transition::Next(RetryApp {
                         state: State::Sleep(component.into()),
@@ -51,13 +54,21 @@ where
     }
 }
 
+pub(super) type Execute<'a> =
+    PrintableSpawnError<'a, PrintableCmdKilled<'a, RecordedAttempt<'a, CmdExecutor<'a>>>>;
+pub(super) type Wait<'a> = RecordedSleep<'a, WaitSec<'a>>;
+
 #[derive(new)]
 pub struct SharedParams<'a, C> {
     command: &'a str,
+    attempt: usize,
     interval: f64,
     executor: &'a (dyn PipedCmdExecute + Send + Sync),
     sleeper: &'a (dyn Sleep + Send + Sync),
+    history: &'a History,
     inner: C,
+    #[new(default)]
+    name: Option<&'a str>,
 }
 
 #[async_trait::async_trait]
@@ -69,67 +80,136 @@ impl<T: 'static, C: Component<Output = T> + Send + Sync> Component for SharedPar
     }
 }
 
-impl<'a> From<SharedParams<'a, PrintableCmdNotFound<'a, CmdExecutor<'a>>>>
-    for SharedParams<'a, WaitSec<'a>>
-{
-    fn from(state: SharedParams<'a, PrintableCmdNotFound<CmdExecutor>>) -> Self {
+fn execute<'a>(
+    command: &'a str,
+    name: Option<&'a str>,
+    attempt: usize,
+    executor: &'a (dyn PipedCmdExecute + Send + Sync),
+    history: &'a History,
+) -> Execute<'a> {
+    PrintableSpawnError {
+        command,
+        name,
+        inner: PrintableCmdKilled {
+            command,
+            name,
+            inner: RecordedAttempt::new(
+                command,
+                attempt,
+                history,
+                CmdExecutor::new(command, attempt, executor),
+            ),
+        },
+    }
+}
+
+impl<'a> From<SharedParams<'a, Execute<'a>>> for SharedParams<'a, Wait<'a>> {
+    fn from(state: SharedParams<'a, Execute<'a>>) -> Self {
         Self {
-            inner: WaitSec {
-                sec: state.interval,
-                sleeper: state.sleeper,
-            },
+            inner: RecordedSleep::new(
+                state.history,
+                WaitSec {
+                    sec: state.interval,
+                    sleeper: state.sleeper,
+                },
+            ),
             command: state.command,
+            attempt: state.attempt,
             interval: state.interval,
             executor: state.executor,
             sleeper: state.sleeper,
+            history: state.history,
+            name: state.name,
         }
     }
 }
 
-impl<'a> From<SharedParams<'a, WaitSec<'a>>>
-    for SharedParams<'a, PrintableCmdNotFound<'a, CmdExecutor<'a>>>
-{
-    fn from(state: SharedParams<'a, WaitSec>) -> Self {
+impl<'a> From<SharedParams<'a, Wait<'a>>> for SharedParams<'a, Execute<'a>> {
+    fn from(state: SharedParams<'a, Wait<'a>>) -> Self {
         Self {
-            inner: PrintableCmdNotFound {
-                command: state.command,
-                inner: CmdExecutor {
-                    command: state.command,
-                    executor: state.executor,
-                },
-            },
+            inner: execute(
+                state.command,
+                state.name,
+                state.attempt + 1,
+                state.executor,
+                state.history,
+            ),
             command: state.command,
+            attempt: state.attempt + 1,
             interval: state.interval,
             executor: state.executor,
             sleeper: state.sleeper,
+            history: state.history,
+            name: state.name,
         }
     }
 }
 
-impl<'a>
-    RetryApp<
-        SharedParams<'a, PrintableCmdNotFound<'a, CmdExecutor<'a>>>,
-        SharedParams<'a, WaitSec<'a>>,
-    >
-{
+impl<'a> RetryApp<SharedParams<'a, Execute<'a>>, SharedParams<'a, Wait<'a>>> {
     pub fn new(
         command: &'a str,
         count: Option<usize>,
         interval: f64,
         executor: &'a (dyn PipedCmdExecute + Send + Sync),
         sleeper: &'a (dyn Sleep + Send + Sync),
+        history: &'a History,
     ) -> Self {
         Self {
             state: State::ExecuteCommand(SharedParams::new(
                 command,
+                1,
                 interval,
                 executor,
                 sleeper,
-                PrintableCmdNotFound::new(command, CmdExecutor::new(command, executor)),
+                history,
+                execute(command, None, 1, executor, history),
             )),
             count,
         }
     }
+
+    /// Numbers the attempts from `attempt` on instead of 1, continuing those made before.
+    pub fn starting_at(self, attempt: usize) -> Self {
+        let state = match self.state {
+            State::ExecuteCommand(params) => State::ExecuteCommand(SharedParams {
+                inner: execute(
+                    params.command,
+                    params.name,
+                    attempt,
+                    params.executor,
+                    params.history,
+                ),
+                attempt,
+                ..params
+            }),
+            State::Sleep(params) => State::Sleep(SharedParams { attempt, ..params }),
+        };
+
+        Self { state, ..self }
+    }
+
+    /// Shows `[name]` before the diagnostics of the command, as in front of its output.
+    pub fn with_name(self, name: &'a str) -> Self {
+        let state = match self.state {
+            State::ExecuteCommand(params) => State::ExecuteCommand(SharedParams {
+                inner: execute(
+                    params.command,
+                    Some(name),
+                    params.attempt,
+                    params.executor,
+                    params.history,
+                ),
+                name: Some(name),
+                ..params
+            }),
+            State::Sleep(params) => State::Sleep(SharedParams {
+                name: Some(name),
+                ..params
+            }),
+        };
+
+        Self { state, ..self }
+    }
 }
 
 #[cfg(test)]
@@ -153,9 +233,7 @@ mod tests {
     #[async_trait::async_trait]
     impl Component for TestS {
         type Output = ();
-        async fn handle(&self) -> Self::Output {
-            ()
-        }
+        async fn handle(&self) -> Self::Output {}
     }
 
     impl From<TestE> for TestS {
@@ -167,7 +245,7 @@ mod tests {
     impl From<TestS> for TestE {
         fn from(_: TestS) -> Self {
             TestE {
-                output: Box::new(|| Ok(Exit::new(1))),
+                output: Box::new(|| Ok(Exit::Code(1))),
             }
         }
     }
@@ -176,7 +254,7 @@ mod tests {
     async fn exec_cmd_to_done_with_success() {
         let app = RetryApp::<TestE, TestS> {
             state: State::ExecuteCommand(TestE {
-                output: Box::new(|| Ok(Exit::new(0))),
+                output: Box::new(|| Ok(Exit::Code(0))),
             }),
             count: None,
         };
@@ -191,7 +269,7 @@ mod tests {
     async fn exec_cmd_to_sleep_without_limit() {
         let app = RetryApp::<TestE, TestS> {
             state: State::ExecuteCommand(TestE {
-                output: Box::new(|| Ok(Exit::new(1))),
+                output: Box::new(|| Ok(Exit::Code(1))),
             }),
             count: None,
         };
@@ -199,10 +277,7 @@ mod tests {
         let next = app.handle().await;
 
         assert!(match &next {
-            Transition::Next(a) => match a.state {
-                State::Sleep(_) => true,
-                _ => false,
-            },
+            Transition::Next(a) => matches!(a.state, State::Sleep(_)),
             _ => false,
         });
     }
@@ -211,7 +286,7 @@ mod tests {
     async fn exec_cmd_to_sleep_with_limit() {
         let app = RetryApp::<TestE, TestS> {
             state: State::ExecuteCommand(TestE {
-                output: Box::new(|| Ok(Exit::new(1))),
+                output: Box::new(|| Ok(Exit::Code(1))),
             }),
             count: Some(2),
         };
@@ -219,10 +294,7 @@ mod tests {
         let next = app.handle().await;
 
         assert!(match &next {
-            Transition::Next(a) => match a.state {
-                State::Sleep(_) => true,
-                _ => false,
-            },
+            Transition::Next(a) => matches!(a.state, State::Sleep(_)),
             _ => false,
         });
 
@@ -239,7 +311,7 @@ mod tests {
     async fn exec_cmd_to_done_with_fail() {
         let app = RetryApp::<TestE, TestS> {
             state: State::ExecuteCommand(TestE {
-                output: Box::new(|| Ok(Exit::new(1))),
+                output: Box::new(|| Ok(Exit::Code(1))),
             }),
             count: Some(1),
         };
@@ -250,6 +322,27 @@ mod tests {
         ));
     }
 
+    #[cfg(unix)]
+    #[lite_async_test::async_test]
+    async fn exec_cmd_to_done_when_limit_exceeded() {
+        let app = RetryApp::<TestE, TestS> {
+            state: State::ExecuteCommand(TestE {
+                output: Box::new(|| {
+                    Ok(Exit::Signal {
+                        signal: libc::SIGXCPU,
+                        core_dumped: false,
+                    })
+                }),
+            }),
+            count: None,
+        };
+
+        assert!(matches!(
+            app.handle().await,
+            Transition::Done(RetryResult::Failure)
+        ));
+    }
+
     #[lite_async_test::async_test]
     async fn sleep_to_exec() {
         let app = RetryApp::<TestE, TestS> {
@@ -258,10 +351,7 @@ mod tests {
         };
 
         assert!(match app.handle().await {
-            Transition::Next(a) => match a.state {
-                State::ExecuteCommand(_) => true,
-                _ => false,
-            },
+            Transition::Next(a) => matches!(a.state, State::ExecuteCommand(_)),
             _ => false,
         });
     }
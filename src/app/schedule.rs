@@ -0,0 +1,327 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+
+/// Timezone the schedule is evaluated in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timezone {
+    /// the system timezone, which follows `TZ`
+    Local,
+    Utc,
+    Fixed(chrono::FixedOffset),
+}
+
+impl std::str::FromStr for Timezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid timezone '{}'", s);
+
+        match s.to_ascii_lowercase().as_str() {
+            "local" => return Ok(Timezone::Local),
+            "utc" | "z" => return Ok(Timezone::Utc),
+            _ => (),
+        }
+
+        let (sign, offset) = match s.split_at(s.find(['+', '-']).ok_or_else(invalid)?) {
+            ("", offset) => offset.split_at(1),
+            _ => return Err(invalid()),
+        };
+        let (hours, minutes) = match offset.split_once(':') {
+            Some(split) => split,
+            None if offset.len() == 4 => offset.split_at(2),
+            None => (offset, "0"),
+        };
+
+        let hours = hours.parse::<i32>().map_err(|_| invalid())?;
+        let minutes = minutes.parse::<i32>().map_err(|_| invalid())?;
+        let seconds = (hours * 60 + minutes) * 60 * if sign == "-" { -1 } else { 1 };
+
+        chrono::FixedOffset::east_opt(seconds)
+            .map(Timezone::Fixed)
+            .ok_or_else(invalid)
+    }
+}
+
+impl Timezone {
+    fn local_time(self, time: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Timezone::Local => time.with_timezone(&chrono::Local).naive_local(),
+            Timezone::Utc => time.naive_utc(),
+            Timezone::Fixed(offset) => time.with_timezone(&offset).naive_local(),
+        }
+    }
+
+    /// None when the time is skipped by a DST change. The earlier one of repeated times is taken.
+    fn resolve(self, time: &NaiveDateTime) -> Option<DateTime<Utc>> {
+        match self {
+            Timezone::Local => chrono::Local
+                .from_local_datetime(time)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc)),
+            Timezone::Utc => Some(Utc.from_utc_datetime(time)),
+            Timezone::Fixed(offset) => offset
+                .from_local_datetime(time)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc)),
+        }
+    }
+}
+
+/// Values allowed in one field of the expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Field {
+    bits: u64,
+    /// false when the field starts with `*`
+    restricted: bool,
+}
+
+impl Field {
+    fn parse(text: &str, min: u32, max: u32, names: &[&str]) -> Result<Self, String> {
+        let invalid = || format!("invalid field '{}'", text);
+        let value = |s: &str| -> Result<u32, String> {
+            let lower = s.to_ascii_lowercase();
+            let value = match names.iter().position(|name| *name == lower) {
+                Some(i) => i as u32 + min,
+                None => s.parse::<u32>().map_err(|_| invalid())?,
+            };
+            match value {
+                value if (min..=max).contains(&value) => Ok(value),
+                _ => Err(format!("'{}' is out of range {}-{}", s, min, max)),
+            }
+        };
+
+        let mut bits = 0;
+
+        for item in text.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => match step.parse::<u32>() {
+                    Ok(step) if step > 0 => (range, step),
+                    _ => return Err(invalid()),
+                },
+                None => (item, 1),
+            };
+
+            let (first, last) = match range.split_once('-') {
+                _ if range == "*" => (min, max),
+                Some((first, last)) => (value(first)?, value(last)?),
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            };
+
+            if first > last {
+                return Err(invalid());
+            }
+
+            for value in (first..=last).step_by(step as usize) {
+                bits |= 1 << value;
+            }
+        }
+
+        Ok(Self {
+            bits,
+            restricted: !text.starts_with('*'),
+        })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+}
+
+/// Cron expression with minute, hour, day of month, month and day of week fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    minute: Field,
+    hour: Field,
+    day: Field,
+    month: Field,
+    weekday: Field,
+}
+
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Give up looking for the next match this far ahead, e.g. for `0 0 30 2 *`.
+const SEARCH_YEARS: i32 = 5;
+
+impl std::str::FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = match s.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+
+        let fields = expression.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 5 {
+            return Err(format!(
+                "invalid cron expression '{}': expected 5 fields, got {}",
+                s,
+                fields.len()
+            ));
+        }
+
+        let mut weekday = Field::parse(fields[4], 0, 7, WEEKDAYS)?;
+        if weekday.matches(7) {
+            weekday.bits |= 1;
+        }
+
+        Ok(Self {
+            minute: Field::parse(fields[0], 0, 59, &[])?,
+            hour: Field::parse(fields[1], 0, 23, &[])?,
+            day: Field::parse(fields[2], 1, 31, &[])?,
+            month: Field::parse(fields[3], 1, 12, MONTHS)?,
+            weekday,
+        })
+    }
+}
+
+impl Schedule {
+    /// As with cron, a day matches either field when both day fields are restricted.
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.day.matches(date.day());
+        let weekday = self.weekday.matches(date.weekday().num_days_from_sunday());
+
+        match (self.day.restricted, self.weekday.restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// First match strictly after `time`, or None when there is none within a few years.
+    pub fn after(&self, time: DateTime<Utc>, timezone: Timezone) -> Option<DateTime<Utc>> {
+        let start = timezone.local_time(time);
+        let mut next =
+            start.date().and_hms_opt(start.hour(), start.minute(), 0)? + Duration::minutes(1);
+
+        while next.year() <= start.year() + SEARCH_YEARS {
+            let date = next.date();
+
+            if !self.month.matches(date.month()) {
+                let (year, month) = match date.month() {
+                    12 => (date.year() + 1, 1),
+                    month => (date.year(), month + 1),
+                };
+                next = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.matches_day(date) {
+                next = date.succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !self.hour.matches(next.hour()) {
+                next = date.and_hms_opt(next.hour(), 0, 0)? + Duration::hours(1);
+            } else if !self.minute.matches(next.minute()) {
+                next += Duration::minutes(1);
+            } else {
+                match timezone.resolve(&next) {
+                    Some(matched) if matched > time => return Some(matched),
+                    _ => next += Duration::minutes(1),
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn next(expression: &str, time: &str) -> Option<DateTime<Utc>> {
+        expression
+            .parse::<Schedule>()
+            .unwrap()
+            .after(utc(time), Timezone::Utc)
+    }
+
+    #[test]
+    fn parse_timezone() {
+        let offset = |seconds| {
+            Ok(Timezone::Fixed(
+                chrono::FixedOffset::east_opt(seconds).unwrap(),
+            ))
+        };
+
+        assert_eq!("local".parse(), Ok(Timezone::Local));
+        assert_eq!("UTC".parse(), Ok(Timezone::Utc));
+        assert_eq!("+09:00".parse(), offset(9 * 3600));
+        assert_eq!("-0530".parse(), offset(-(5 * 3600 + 30 * 60)));
+        assert_eq!("+2".parse(), offset(2 * 3600));
+        assert!("Mars/Olympus".parse::<Timezone>().is_err());
+        assert!("9+00".parse::<Timezone>().is_err());
+    }
+
+    #[test]
+    fn parse_schedule() {
+        assert!("*/5 * * * *".parse::<Schedule>().is_ok());
+        assert!("0 9-17 * jan-jun mon,fri".parse::<Schedule>().is_ok());
+        assert!("@daily".parse::<Schedule>().is_ok());
+        assert!("* * * *".parse::<Schedule>().is_err());
+        assert!("60 * * * *".parse::<Schedule>().is_err());
+        assert!("*/0 * * * *".parse::<Schedule>().is_err());
+        assert!("5-1 * * * *".parse::<Schedule>().is_err());
+    }
+
+    #[test]
+    fn next_match_with_step() {
+        assert_eq!(
+            next("*/5 * * * *", "2024-01-01T00:03:30Z"),
+            Some(utc("2024-01-01T00:05:00Z"))
+        );
+        assert_eq!(
+            next("*/5 * * * *", "2024-01-01T00:05:00Z"),
+            Some(utc("2024-01-01T00:10:00Z"))
+        );
+    }
+
+    #[test]
+    fn next_match_across_month_and_year() {
+        assert_eq!(
+            next("30 6 1 * *", "2024-01-31T12:00:00Z"),
+            Some(utc("2024-02-01T06:30:00Z"))
+        );
+        assert_eq!(
+            next("@yearly", "2024-06-15T00:00:00Z"),
+            Some(utc("2025-01-01T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn next_match_on_either_day_field() {
+        // 2024-01-05 is a Friday
+        assert_eq!(
+            next("0 0 10 * fri", "2024-01-01T00:00:00Z"),
+            Some(utc("2024-01-05T00:00:00Z"))
+        );
+        assert_eq!(
+            next("0 0 * * 7", "2024-01-01T00:00:00Z"),
+            Some(utc("2024-01-07T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn next_match_in_fixed_offset() {
+        let schedule = "0 9 * * *".parse::<Schedule>().unwrap();
+        let timezone = "+09:00".parse().unwrap();
+
+        assert_eq!(
+            schedule.after(utc("2024-01-01T01:00:00Z"), timezone),
+            Some(utc("2024-01-02T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn no_match_for_impossible_date() {
+        assert_eq!(next("0 0 30 2 *", "2024-01-01T00:00:00Z"), None);
+    }
+}
@@ -46,17 +46,23 @@ where
     }
 }
 
+pub(super) type Execute<'a> =
+    PrintableSpawnError<'a, PrintableCmdKilled<'a, RecordedAttempt<'a, CmdExecutor<'a>>>>;
+pub(super) type Wait<'a> = RecordedSleep<'a, WaitSec<'a>>;
+
 #[derive(new)]
 pub struct SharedParams<'a, C> {
     command: &'a str,
+    attempt: usize,
     interval: f64,
     executor: &'a (dyn PipedCmdExecute + Send + Sync),
     sleeper: &'a (dyn Sleep + Send + Sync),
+    history: &'a History,
     inner: C,
 }
 
 #[async_trait::async_trait]
-impl<T: 'static, C: Component<Output = T> + Send + Sync, 'a> Component for SharedParams<'a, C> {
+impl<T: 'static, C: Component<Output = T> + Send + Sync> Component for SharedParams<'_, C> {
     type Output = T;
 
     async fn handle(&self) -> Self::Output {
@@ -64,63 +70,83 @@ impl<T: 'static, C: Component<Output = T> + Send + Sync, 'a> Component for Share
     }
 }
 
-impl<'a> From<SharedParams<'a, PrintableCmdNotFound<'a, CmdExecutor<'a>>>>
-    for SharedParams<'a, WaitSec<'a>>
-{
-    fn from(state: SharedParams<'a, PrintableCmdNotFound<'a, CmdExecutor<'a>>>) -> Self {
+fn execute<'a>(
+    command: &'a str,
+    attempt: usize,
+    executor: &'a (dyn PipedCmdExecute + Send + Sync),
+    history: &'a History,
+) -> Execute<'a> {
+    PrintableSpawnError::new(
+        command,
+        PrintableCmdKilled::new(
+            command,
+            RecordedAttempt::new(
+                command,
+                attempt,
+                history,
+                CmdExecutor::new(command, attempt, executor),
+            ),
+        ),
+    )
+}
+
+impl<'a> From<SharedParams<'a, Execute<'a>>> for SharedParams<'a, Wait<'a>> {
+    fn from(state: SharedParams<'a, Execute<'a>>) -> Self {
         Self {
-            inner: WaitSec {
-                sec: state.interval,
-                sleeper: state.sleeper,
-            },
+            inner: RecordedSleep::new(
+                state.history,
+                WaitSec {
+                    sec: state.interval,
+                    sleeper: state.sleeper,
+                },
+            ),
             command: state.command,
+            attempt: state.attempt,
             interval: state.interval,
             executor: state.executor,
             sleeper: state.sleeper,
+            history: state.history,
         }
     }
 }
 
-impl<'a> From<SharedParams<'a, WaitSec<'a>>>
-    for SharedParams<'a, PrintableCmdNotFound<'a, CmdExecutor<'a>>>
-{
-    fn from(state: SharedParams<'a, WaitSec<'a>>) -> Self {
+impl<'a> From<SharedParams<'a, Wait<'a>>> for SharedParams<'a, Execute<'a>> {
+    fn from(state: SharedParams<'a, Wait<'a>>) -> Self {
         Self {
-            inner: PrintableCmdNotFound {
-                command: state.command,
-                inner: CmdExecutor {
-                    command: state.command,
-                    executor: state.executor,
-                },
-            },
+            inner: execute(
+                state.command,
+                state.attempt + 1,
+                state.executor,
+                state.history,
+            ),
             command: state.command,
+            attempt: state.attempt + 1,
             interval: state.interval,
             executor: state.executor,
             sleeper: state.sleeper,
+            history: state.history,
         }
     }
 }
 
-impl<'a>
-    SuperviseApp<
-        SharedParams<'a, PrintableCmdNotFound<'a, CmdExecutor<'a>>>,
-        SharedParams<'a, WaitSec<'a>>,
-    >
-{
+impl<'a> SuperviseApp<SharedParams<'a, Execute<'a>>, SharedParams<'a, Wait<'a>>> {
     pub fn new(
         command: &'a str,
         count: Option<usize>,
         interval: f64,
         executor: &'a (dyn PipedCmdExecute + Send + Sync),
         sleeper: &'a (dyn Sleep + Send + Sync),
+        history: &'a History,
     ) -> Self {
         Self {
             state: State::ExecuteCommand(SharedParams::new(
                 command,
+                1,
                 interval,
                 executor,
                 sleeper,
-                PrintableCmdNotFound::new(command, CmdExecutor::new(command, executor)),
+                history,
+                execute(command, 1, executor, history),
             )),
             count,
         }
@@ -137,7 +163,7 @@ mod tests {
     impl Component for TestE {
         type Output = anyhow::Result<Exit>;
         async fn handle(&self) -> Self::Output {
-            Ok(Exit::new(0))
+            Ok(Exit::Code(0))
         }
     }
 
@@ -146,9 +172,7 @@ mod tests {
     #[async_trait::async_trait]
     impl Component for TestS {
         type Output = ();
-        async fn handle(&self) -> Self::Output {
-            ()
-        }
+        async fn handle(&self) -> Self::Output {}
     }
 
     impl From<TestE> for TestS {
@@ -173,10 +197,7 @@ mod tests {
         let next = app.handle().await;
 
         assert!(match &next {
-            Transition::Next(a) => match a.state {
-                State::Sleep(_) => true,
-                _ => false,
-            },
+            Transition::Next(a) => matches!(a.state, State::Sleep(_)),
             _ => false,
         });
     }
@@ -191,10 +212,7 @@ mod tests {
         let next = app.handle().await;
 
         assert!(match &next {
-            Transition::Next(a) => match a.state {
-                State::Sleep(_) => true,
-                _ => false,
-            },
+            Transition::Next(a) => matches!(a.state, State::Sleep(_)),
             _ => false,
         });
     }
@@ -206,10 +224,7 @@ mod tests {
             count: Some(1),
         };
 
-        assert!(match app.handle().await {
-            Transition::Done(_) => true,
-            _ => false,
-        });
+        assert!(matches!(app.handle().await, Transition::Done(_)));
     }
 
     #[lite_async_test::async_test]
@@ -220,10 +235,7 @@ mod tests {
         };
 
         assert!(match app.handle().await {
-            Transition::Next(a) => match a.state {
-                State::ExecuteCommand(_) => true,
-                _ => false,
-            },
+            Transition::Next(a) => matches!(a.state, State::ExecuteCommand(_)),
             _ => false,
         });
     }
@@ -0,0 +1,96 @@
+use super::{components::*, *};
+use crate::io::*;
+
+/// Exit code used when the command ran into the timeout, as with coreutils' `timeout`.
+pub const TIMED_OUT: i32 = 124;
+
+#[derive(Debug, PartialEq)]
+pub enum TimeoutResult {
+    /// the command finished in time
+    Exited(Exit),
+    /// the command was signalled after running into the timeout
+    TimedOut(Exit),
+    /// the command could not be found
+    NotFound,
+    /// the command was found but could not be started
+    NotExecutable,
+}
+
+impl TimeoutResult {
+    /// Exit code of cx. A timed out command exits with 124 unless `preserve_status` is set.
+    pub fn code(&self, preserve_status: bool) -> i32 {
+        match self {
+            TimeoutResult::Exited(exit) => exit.status(),
+            TimeoutResult::TimedOut(exit) if preserve_status => exit.status(),
+            TimeoutResult::TimedOut(_) => TIMED_OUT,
+            TimeoutResult::NotFound => 127,
+            TimeoutResult::NotExecutable => 126,
+        }
+    }
+}
+
+/// Runs the command once, signalling it when it runs into the timeout.
+#[derive(new)]
+pub struct TimeoutApp<'a> {
+    command: &'a str,
+    timeout: Timeout,
+    executor: &'a (dyn TimedCmdExecute + Send + Sync),
+}
+
+#[async_trait::async_trait]
+impl StateMachine for TimeoutApp<'_> {
+    type Output = TimeoutResult;
+
+    async fn handle(self) -> Transition<Self, Self::Output> {
+        let component = PrintableSpawnError::new(
+            self.command,
+            TimedCmdExecutor::new(self.command, &self.timeout, self.executor),
+        );
+
+        let result = match component.handle().await {
+            Ok(TimedExit {
+                exit,
+                timed_out: true,
+            }) => TimeoutResult::TimedOut(exit),
+            Ok(TimedExit { exit, .. }) => TimeoutResult::Exited(exit),
+            Err(e) => match e.downcast_ref::<std::io::Error>().map(|e| e.kind()) {
+                Some(std::io::ErrorKind::NotFound) => TimeoutResult::NotFound,
+                _ => TimeoutResult::NotExecutable,
+            },
+        };
+
+        Transition::Done(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_code_of_result() {
+        let killed = Exit::Signal {
+            signal: 9,
+            core_dumped: false,
+        };
+
+        assert_eq!(TimeoutResult::Exited(Exit::Code(3)).code(false), 3);
+        assert_eq!(TimeoutResult::Exited(killed.clone()).code(false), 137);
+        assert_eq!(TimeoutResult::TimedOut(killed.clone()).code(false), 124);
+        assert_eq!(TimeoutResult::TimedOut(killed).code(true), 137);
+        assert_eq!(TimeoutResult::NotFound.code(false), 127);
+    }
+
+    #[tokio::test]
+    async fn report_missing_command() {
+        let timeout = Timeout {
+            after: std::time::Duration::from_secs(5),
+            signal: Signal(15),
+            kill_after: None,
+        };
+        let executor = PipedCmdExecutor::default();
+        let app = TimeoutApp::new("cx-missing-command", timeout, &executor);
+        let actual = run(app).await;
+        assert_eq!(actual, TimeoutResult::NotFound);
+    }
+}
@@ -0,0 +1,298 @@
+use super::{components::*, *};
+use crate::io::*;
+use std::path::PathBuf;
+use tokio::time::Instant;
+
+/// How long a single TCP connection attempt may take.
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Something to wait for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// a TCP connection to `host:port` can be established
+    Tcp(String),
+    /// the path exists
+    File(PathBuf),
+    /// the command exits with 0
+    Command(String),
+}
+
+impl std::str::FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid condition '{}'", s);
+        let (kind, target) = s.split_once(':').ok_or_else(invalid)?;
+
+        match kind {
+            "tcp" => match target.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                    Ok(Condition::Tcp(target.to_string()))
+                }
+                _ => Err(format!("invalid address '{}', expected host:port", target)),
+            },
+            "file" if !target.is_empty() => Ok(Condition::File(target.into())),
+            "cmd" if !target.is_empty() => Ok(Condition::Command(target.to_string())),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::Tcp(address) => write!(f, "tcp:{}", address),
+            Condition::File(path) => write!(f, "file:{}", path.display()),
+            Condition::Command(command) => write!(f, "cmd:{}", command),
+        }
+    }
+}
+
+impl Condition {
+    /// Fails only when the command of the condition could not be spawned, which is reported.
+    async fn check(
+        &self,
+        attempt: usize,
+        executor: &(dyn PipedCmdExecute + Send + Sync),
+    ) -> anyhow::Result<bool> {
+        match self {
+            Condition::Tcp(address) => {
+                let address = address.clone();
+                let connect = move || {
+                    use std::net::ToSocketAddrs;
+
+                    let mut addresses = match address.to_socket_addrs() {
+                        Ok(addresses) => addresses,
+                        Err(_) => return false,
+                    };
+                    addresses.any(|address| {
+                        std::net::TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).is_ok()
+                    })
+                };
+                Ok(tokio::task::spawn_blocking(connect).await.unwrap_or(false))
+            }
+            Condition::File(path) => Ok(tokio::fs::metadata(path).await.is_ok()),
+            Condition::Command(command) => {
+                let execute = CmdExecutor::new(command, attempt, executor);
+                let exit = PrintableSpawnError::new(command, execute).handle().await?;
+                Ok(exit.success())
+            }
+        }
+    }
+}
+
+pub enum WaitForResult {
+    Ready,
+    /// the conditions not met when time ran out
+    TimedOut(Vec<Condition>),
+    /// the command of a condition could not be spawned, which polling would not change
+    SpawnFailed(anyhow::Error),
+}
+
+enum State {
+    Check,
+    Sleep,
+}
+
+/// Polls the conditions every `interval` seconds until all of them, or any of them when `any`
+/// is set, have been met. A met condition is not checked again.
+pub struct WaitForApp<'a> {
+    conditions: &'a [Condition],
+    met: Vec<bool>,
+    any: bool,
+    interval: f64,
+    deadline: Option<Instant>,
+    attempt: usize,
+    state: State,
+    executor: &'a (dyn PipedCmdExecute + Send + Sync),
+    sleeper: &'a (dyn Sleep + Send + Sync),
+}
+
+impl<'a> WaitForApp<'a> {
+    pub fn new(
+        conditions: &'a [Condition],
+        any: bool,
+        interval: f64,
+        timeout: Option<std::time::Duration>,
+        executor: &'a (dyn PipedCmdExecute + Send + Sync),
+        sleeper: &'a (dyn Sleep + Send + Sync),
+    ) -> Self {
+        Self {
+            conditions,
+            met: vec![false; conditions.len()],
+            any,
+            interval,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            attempt: 0,
+            state: State::Check,
+            executor,
+            sleeper,
+        }
+    }
+
+    fn ready(&self) -> bool {
+        match self.any {
+            true => self.met.iter().any(|met| *met),
+            false => self.met.iter().all(|met| *met),
+        }
+    }
+
+    fn timed_out(&self) -> WaitForResult {
+        let unmet = self
+            .conditions
+            .iter()
+            .zip(&self.met)
+            .filter(|(_, met)| !**met)
+            .map(|(condition, _)| condition.clone())
+            .collect();
+
+        WaitForResult::TimedOut(unmet)
+    }
+}
+
+#[async_trait::async_trait]
+impl StateMachine for WaitForApp<'_> {
+    type Output = WaitForResult;
+
+    async fn handle(mut self) -> Transition<Self, Self::Output> {
+        match self.state {
+            State::Check => {
+                self.attempt += 1;
+
+                for (condition, met) in self.conditions.iter().zip(self.met.iter_mut()) {
+                    if *met {
+                        continue;
+                    }
+
+                    let check = condition.check(self.attempt, self.executor);
+                    let checked = match self.deadline {
+                        Some(deadline) => tokio::time::timeout_at(deadline, check)
+                            .await
+                            .unwrap_or(Ok(false)),
+                        None => check.await,
+                    };
+                    *met = match checked {
+                        Ok(checked) => checked,
+                        Err(e) => return Transition::Done(WaitForResult::SpawnFailed(e)),
+                    };
+                }
+
+                if self.ready() {
+                    return Transition::Done(WaitForResult::Ready);
+                }
+
+                Transition::Next(Self {
+                    state: State::Sleep,
+                    ..self
+                })
+            }
+            State::Sleep => {
+                let sec = match self.deadline {
+                    Some(deadline) => {
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        if remaining.is_zero() {
+                            return Transition::Done(self.timed_out());
+                        }
+                        self.interval.min(remaining.as_secs_f64())
+                    }
+                    None => self.interval,
+                };
+
+                WaitSec {
+                    sec,
+                    sleeper: self.sleeper,
+                }
+                .handle()
+                .await;
+
+                Transition::Next(Self {
+                    state: State::Check,
+                    ..self
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn parse_conditions() {
+        assert_eq!(
+            "tcp:localhost:5432".parse(),
+            Ok(Condition::Tcp("localhost:5432".into()))
+        );
+        assert_eq!(
+            "tcp:[::1]:80".parse(),
+            Ok(Condition::Tcp("[::1]:80".into()))
+        );
+        assert_eq!(
+            "file:/tmp/ready".parse(),
+            Ok(Condition::File("/tmp/ready".into()))
+        );
+        assert_eq!(
+            "cmd:pg_isready -h db".parse(),
+            Ok(Condition::Command("pg_isready -h db".into()))
+        );
+        assert!("tcp:localhost".parse::<Condition>().is_err());
+        assert!("tcp:localhost:http".parse::<Condition>().is_err());
+        assert!("http://localhost".parse::<Condition>().is_err());
+        assert!("file:".parse::<Condition>().is_err());
+    }
+
+    #[tokio::test]
+    async fn ready_when_listening() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let conditions = [Condition::Tcp(address.to_string())];
+        let executor = PipedCmdExecutor::default();
+
+        let app = WaitForApp::new(&conditions, false, 0.01, None, &executor, &Sleeper);
+
+        assert!(matches!(run(app).await, WaitForResult::Ready));
+    }
+
+    #[tokio::test]
+    async fn time_out_with_unmet_conditions() {
+        let dir = tempfile::tempdir().unwrap();
+        let present = dir.path().to_path_buf();
+        let missing = dir.path().join("missing");
+        let conditions = [Condition::File(present), Condition::File(missing.clone())];
+        let executor = PipedCmdExecutor::default();
+
+        let timeout = Some(Duration::from_millis(100));
+        let app = WaitForApp::new(&conditions, false, 0.01, timeout, &executor, &Sleeper);
+
+        match run(app).await {
+            WaitForResult::TimedOut(unmet) => assert_eq!(unmet, [Condition::File(missing)]),
+            _ => panic!("should time out"),
+        }
+    }
+
+    #[tokio::test]
+    async fn ready_when_any_condition_met() {
+        let conditions = [
+            Condition::File("/cx-non-existent-file".into()),
+            Condition::Command("true".into()),
+        ];
+        let executor = PipedCmdExecutor::default();
+
+        let timeout = Some(Duration::from_secs(5));
+        let app = WaitForApp::new(&conditions, true, 0.01, timeout, &executor, &Sleeper);
+
+        assert!(matches!(run(app).await, WaitForResult::Ready));
+    }
+
+    #[tokio::test]
+    async fn fail_when_command_cannot_be_spawned() {
+        let conditions = [Condition::Command("cx-non-existent-command".into())];
+        let executor = PipedCmdExecutor::default();
+
+        let app = WaitForApp::new(&conditions, false, 0.01, None, &executor, &Sleeper);
+
+        assert!(matches!(run(app).await, WaitForResult::SpawnFailed(_)));
+    }
+}
@@ -1,3 +1,8 @@
+use crate::app::{Condition, Halt, Missed, Schedule, SummaryFormat, Timezone};
+use crate::io::{EventsTarget, LimitPolicy, LogTarget, OutputMode, Prefix, RotatePeriod, Signal};
+use std::path::PathBuf;
+use std::time::Duration;
+
 #[derive(Debug, structopt::StructOpt, PartialEq)]
 #[structopt(setting = structopt::clap::AppSettings::AllowLeadingHyphen)]
 /// Command eXecutor
@@ -12,6 +17,9 @@ pub enum Config {
         #[structopt(short, long, default_value = "0.1")]
         interval: f64,
 
+        #[structopt(flatten)]
+        exec: ExecOptions,
+
         /// command and options
         #[structopt(name = "COMMAND")]
         command: Vec<String>,
@@ -26,8 +34,490 @@ pub enum Config {
         #[structopt(short, long, default_value = "0.1")]
         interval: f64,
 
+        #[structopt(flatten)]
+        exec: ExecOptions,
+
+        /// command and options
+        #[structopt(name = "COMMAND")]
+        command: Vec<String>,
+    },
+    /// Run commands in parallel.
+    parallel {
+        /// number of jobs run at once [default: number of CPUs]
+        #[structopt(short, long)]
+        jobs: Option<usize>,
+
+        /// when to stop: never, now,fail=N, soon,fail=N, now,success=N or soon,success=N
+        #[structopt(long, default_value = "never")]
+        halt: Halt,
+
+        /// number of retries of each failed job
+        #[structopt(long, default_value = "0")]
+        retry: usize,
+
+        /// retry interval (sec)
+        #[structopt(short, long, default_value = "0.1")]
+        interval: f64,
+
+        /// read commands from the file, one per line ("-" for stdin)
+        #[structopt(short = "a", long)]
+        file: Option<PathBuf>,
+
+        /// commands separated by ::: (read from stdin when omitted)
+        #[structopt(name = "COMMAND")]
+        command: Vec<String>,
+    },
+    /// Run a command for each input item in parallel.
+    map {
+        /// number of items processed at once [default: number of CPUs]
+        #[structopt(short, long)]
+        jobs: Option<usize>,
+
+        /// number of retries of each failed item
+        #[structopt(long, default_value = "0")]
+        retry: usize,
+
+        /// retry interval (sec)
+        #[structopt(short, long, default_value = "0.1")]
+        interval: f64,
+
+        /// read items from the file, one per line (stdin when omitted or "-")
+        #[structopt(short = "a", long)]
+        file: Option<PathBuf>,
+
+        /// write whether each item succeeded or failed to the file, tab-separated
+        #[structopt(long)]
+        results: Option<PathBuf>,
+
+        /// skip the items that succeeded according to the results file
+        #[structopt(long, requires = "results")]
+        resume: bool,
+
+        /// write the items that failed to the file, one per line, to run them again with -a
+        #[structopt(long)]
+        failed: Option<PathBuf>,
+
+        /// command template; {} is replaced with the item, which is appended when missing
+        #[structopt(name = "COMMAND")]
+        command: Vec<String>,
+    },
+    /// Try commands in turn until one of them succeeds.
+    fallback {
+        /// maximum number of attempts of each command, unless given before the command
+        #[structopt(short, long, default_value = "1")]
+        count: usize,
+
+        /// retry interval (sec) of each command, unless given before the command
+        #[structopt(short, long, default_value = "0.1")]
+        interval: f64,
+
+        #[structopt(flatten)]
+        exec: ExecOptions,
+
+        /// commands separated by :::, each optionally starting with its own -c and -i followed by --
+        #[structopt(name = "COMMAND")]
+        command: Vec<String>,
+    },
+    /// Run commands concurrently and take the first one to succeed.
+    race {
+        /// forward the output of every command, prefixed with the command, instead of only the
+        /// output of the one taken
+        #[structopt(long)]
+        all_output: bool,
+
+        /// commands separated by :::
+        #[structopt(name = "COMMAND")]
+        command: Vec<String>,
+    },
+    /// Run a command on a cron schedule.
+    cron {
+        /// cron expression (minute hour day-of-month month day-of-week) or @hourly, @daily, @weekly,
+        /// @monthly, @yearly
+        schedule: Schedule,
+
+        /// timezone of the schedule: local (follows TZ), utc or an offset such as +09:00
+        #[structopt(long, default_value = "local")]
+        timezone: Timezone,
+
+        /// what to do about runs missed while the previous run was in progress: skip or run-once
+        #[structopt(long, default_value = "skip")]
+        missed: Missed,
+
+        /// maximum number of runs
+        #[structopt(short, long)]
+        count: Option<usize>,
+
+        #[structopt(flatten)]
+        exec: ExecOptions,
+
         /// command and options
         #[structopt(name = "COMMAND")]
         command: Vec<String>,
     },
+    /// Wait until conditions are met, then run a command in place of cx.
+    wait_for {
+        /// tcp:<host>:<port>, file:<path> or cmd:<command>
+        #[structopt(name = "CONDITION", required = true)]
+        conditions: Vec<Condition>,
+
+        /// ready when any condition is met instead of all of them
+        #[structopt(long)]
+        any: bool,
+
+        /// polling interval (sec)
+        #[structopt(short, long, default_value = "1")]
+        interval: f64,
+
+        /// give up after this long, e.g. 60s or 5m
+        #[structopt(short, long, parse(try_from_str = parse_duration))]
+        timeout: Option<Duration>,
+
+        /// command and options run once ready, following --
+        #[structopt(name = "COMMAND", last = true)]
+        command: Vec<String>,
+    },
+    /// Run commands repeatedly and report their timing.
+    bench {
+        /// number of timed runs of each command
+        #[structopt(short = "n", long, default_value = "10")]
+        runs: usize,
+
+        /// number of runs before the timed ones
+        #[structopt(short, long, default_value = "0")]
+        warmup: usize,
+
+        /// show the output of the commands instead of discarding it
+        #[structopt(long)]
+        show_output: bool,
+
+        /// write the results as JSON into the file
+        #[structopt(long)]
+        export_json: Option<PathBuf>,
+
+        /// write the results as CSV into the file
+        #[structopt(long)]
+        export_csv: Option<PathBuf>,
+
+        /// write lifecycle events as JSON lines to stderr (json) or a file (json:<path>)
+        #[structopt(long)]
+        events: Option<EventsTarget>,
+
+        /// commands separated by :::, compared with each other when more than one
+        #[structopt(name = "COMMAND")]
+        command: Vec<String>,
+    },
+    /// Run a command repeatedly and measure how often it fails.
+    flaky {
+        /// maximum number of runs
+        #[structopt(short = "n", long, default_value = "20")]
+        runs: usize,
+
+        /// number of runs before stopping early
+        #[structopt(long, default_value = "10")]
+        min_runs: usize,
+
+        /// highest acceptable failure rate, e.g. 0.05 or 5%
+        #[structopt(short, long, default_value = "0", parse(try_from_str = parse_rate))]
+        threshold: f64,
+
+        /// interval between runs (sec)
+        #[structopt(short, long, default_value = "0")]
+        interval: f64,
+
+        /// directory for the output of failing runs [default: a new temporary directory]
+        #[structopt(long)]
+        failures_dir: Option<PathBuf>,
+
+        /// show the output of the command instead of only saving it
+        #[structopt(long)]
+        show_output: bool,
+
+        /// write lifecycle events as JSON lines to stderr (json) or a file (json:<path>)
+        #[structopt(long)]
+        events: Option<EventsTarget>,
+
+        /// command and options
+        #[structopt(name = "COMMAND")]
+        command: Vec<String>,
+    },
+    /// Run a command while holding an exclusive lock on a file.
+    lock {
+        /// lock file, created when missing
+        path: PathBuf,
+
+        /// how long to wait for a held lock, e.g. 30s [default: forever]
+        #[structopt(short, long, parse(try_from_str = parse_duration))]
+        wait: Option<Duration>,
+
+        /// give up at once when the lock is held
+        #[structopt(short, long, conflicts_with = "wait")]
+        no_wait: bool,
+
+        /// command and options, following --
+        #[structopt(name = "COMMAND", last = true)]
+        command: Vec<String>,
+    },
+    /// Run a command with a time limit.
+    timeout {
+        /// time limit, e.g. 30s, 5m or 1h (sec when no unit is given)
+        #[structopt(parse(try_from_str = parse_duration))]
+        duration: Duration,
+
+        /// signal sent on timeout, by name or number
+        #[structopt(short, long, default_value = "TERM")]
+        signal: Signal,
+
+        /// send KILL when the command is still running this long after the signal
+        #[structopt(short, long, parse(try_from_str = parse_duration))]
+        kill_after: Option<Duration>,
+
+        /// exit with the status of the command even when it timed out
+        #[structopt(long)]
+        preserve_status: bool,
+
+        /// run the command in the foreground, letting it read from the terminal and get its
+        /// signals; what the command starts is then not timed out
+        #[structopt(long)]
+        foreground: bool,
+
+        /// command and options
+        #[structopt(name = "COMMAND")]
+        command: Vec<String>,
+    },
+}
+
+#[derive(Debug, structopt::StructOpt, PartialEq)]
+pub struct ExecOptions {
+    /// run command on a pseudo-terminal
+    #[structopt(long)]
+    pub pty: bool,
+
+    /// limit of the child's virtual memory (bytes, accepts K/M/G suffixes)
+    #[structopt(long, parse(try_from_str = parse_size))]
+    pub limit_as: Option<u64>,
+
+    /// limit of the child's CPU time (sec)
+    #[structopt(long)]
+    pub limit_cpu: Option<u64>,
+
+    /// limit of the child's open file descriptors
+    #[structopt(long)]
+    pub limit_nofile: Option<u64>,
+
+    /// limit of the number of processes of the child's user
+    #[structopt(long)]
+    pub limit_nproc: Option<u64>,
+
+    /// limit of the child's core dump size (bytes, accepts K/M/G suffixes)
+    #[structopt(long, parse(try_from_str = parse_size))]
+    pub limit_core: Option<u64>,
+
+    /// run command as the user (name or uid)
+    #[structopt(long)]
+    pub user: Option<String>,
+
+    /// run command as the group (name or gid)
+    #[structopt(long)]
+    pub group: Option<String>,
+
+    /// attempts whose output is shown: all, final, failures or none
+    #[structopt(long, default_value = "all")]
+    pub output: OutputMode,
+
+    /// replace the output of an attempt with a note when identical to the previous attempt
+    #[structopt(long)]
+    pub collapse_repeats: bool,
+
+    /// prefix of each output line: {ts}, {ts:rel}, {attempt}, {pid} and {stream} are replaced
+    #[structopt(long)]
+    pub prefix: Option<Prefix>,
+
+    /// append output of the command to the file
+    #[structopt(long)]
+    pub log_file: Option<PathBuf>,
+
+    /// append stderr of the command to the file instead of --log-file
+    #[structopt(long)]
+    pub stderr_log: Option<PathBuf>,
+
+    /// rotate log files exceeding the size (bytes, accepts K/M/G suffixes)
+    #[structopt(long, parse(try_from_str = parse_size))]
+    pub log_max_size: Option<u64>,
+
+    /// rotate log files periodically: hourly or daily
+    #[structopt(long)]
+    pub log_rotate: Option<RotatePeriod>,
+
+    /// number of rotated log files to keep
+    #[structopt(long, default_value = "5")]
+    pub log_keep: usize,
+
+    /// compress rotated log files with gzip
+    #[structopt(long)]
+    pub log_compress: bool,
+
+    /// write the output and exit metadata of every attempt into the directory
+    #[structopt(long)]
+    pub artifacts_dir: Option<PathBuf>,
+
+    /// send every output line to the system log: syslog or journald
+    #[structopt(long)]
+    pub log_to: Option<LogTarget>,
+
+    /// socket of the system log, instead of /dev/log or /run/systemd/journal/socket
+    #[structopt(long, requires = "log-to")]
+    pub log_socket: Option<PathBuf>,
+
+    /// identifier of the system log messages [default: program name]
+    #[structopt(long, requires = "log-to")]
+    pub log_identifier: Option<String>,
+
+    /// maximum bytes of output of each attempt, such as 10M
+    #[structopt(long, parse(try_from_str = parse_size))]
+    pub max_output_bytes: Option<u64>,
+
+    /// maximum bytes of output over all attempts, such as 1G
+    #[structopt(long, parse(try_from_str = parse_size))]
+    pub max_run_output_bytes: Option<u64>,
+
+    /// what to do with output beyond the maximum: truncate, kill or drop-middle
+    #[structopt(long, default_value = "truncate")]
+    pub output_limit_policy: LimitPolicy,
+
+    /// number of last lines of each stream to show when giving up
+    #[structopt(long)]
+    pub tail_lines: Option<usize>,
+
+    /// write lifecycle events as JSON lines to stderr (json) or a file (json:<path>)
+    #[structopt(long)]
+    pub events: Option<EventsTarget>,
+
+    /// print a report of every attempt at the end of the run: text or json
+    #[structopt(long)]
+    pub summary: Option<SummaryFormat>,
+
+    /// hold an exclusive lock on the file for the whole run
+    #[structopt(long)]
+    pub lock: Option<PathBuf>,
+
+    /// how long to wait for a held lock, e.g. 30s [default: forever]
+    #[structopt(long, requires = "lock", parse(try_from_str = parse_duration))]
+    pub lock_wait: Option<Duration>,
+}
+
+/// Parses a byte size such as `512`, `64K`, `10M` or `2G` (binary units).
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+
+    let multiplier: u64 = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(format!("invalid size unit '{}'", unit)),
+    };
+
+    number
+        .parse::<u64>()
+        .map_err(|e| format!("invalid size '{}': {}", s, e))?
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{}' is too large", s))
+}
+
+/// Parses a duration such as `30s`, `1.5m`, `2h` or `1d`. A bare number is in seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+
+    let multiplier = match unit {
+        "" | "s" => 1.0,
+        "ms" => 0.001,
+        "m" => 60.0,
+        "h" => 60.0 * 60.0,
+        "d" => 24.0 * 60.0 * 60.0,
+        _ => return Err(format!("invalid duration unit '{}'", unit)),
+    };
+
+    let number = number
+        .parse::<f64>()
+        .map_err(|e| format!("invalid duration '{}': {}", s, e))?;
+
+    Duration::try_from_secs_f64(number * multiplier)
+        .map_err(|e| format!("invalid duration '{}': {}", s, e))
+}
+
+/// Parses a rate such as `0.05` or `5%`, between 0 and 1.
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let rate = match s.strip_suffix('%') {
+        Some(percent) => percent.parse::<f64>().map(|percent| percent / 100.0),
+        None => s.parse::<f64>(),
+    }
+    .map_err(|e| format!("invalid rate '{}': {}", s, e))?;
+
+    match (0.0..=1.0).contains(&rate) {
+        true => Ok(rate),
+        false => Err(format!(
+            "invalid rate '{}', expected 0 to 1 or 0% to 100%",
+            s
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_size_without_unit() {
+        assert_eq!(parse_size("512"), Ok(512));
+    }
+
+    #[test]
+    fn parse_size_with_unit() {
+        assert_eq!(parse_size("64K"), Ok(64 * 1024));
+        assert_eq!(parse_size("10M"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_size("2g"), Ok(2 * 1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn parse_size_rejects_invalid_input() {
+        assert!(parse_size("").is_err());
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn parse_duration_with_unit() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn parse_duration_rejects_invalid_input() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("10x").is_err());
+        assert!(parse_duration("s").is_err());
+    }
+
+    #[test]
+    fn parse_rate_as_fraction_or_percent() {
+        assert_eq!(parse_rate("0.05"), Ok(0.05));
+        assert_eq!(parse_rate("5%"), Ok(0.05));
+        assert_eq!(parse_rate("0"), Ok(0.0));
+        assert!(parse_rate("1.5").is_err());
+        assert!(parse_rate("-1%").is_err());
+        assert!(parse_rate("x").is_err());
+    }
 }
@@ -1,18 +1,195 @@
+mod artifacts;
+mod attempt;
+mod capture;
+#[cfg(unix)]
+mod credentials;
+mod events;
+mod limit;
+#[cfg(unix)]
+mod lock;
+mod log_file;
+mod output;
+#[cfg(unix)]
+mod process_group;
+#[cfg(unix)]
+mod pty;
+mod repeat;
+mod rlimit;
+mod rusage;
+mod signal;
+mod system_log;
+mod tail;
 mod tokio_impl;
 
+pub use artifacts::*;
+pub use attempt::*;
+pub use capture::*;
+#[cfg(unix)]
+pub use credentials::*;
+pub use events::*;
+pub use limit::*;
+#[cfg(unix)]
+pub use lock::*;
+pub use log_file::*;
+pub use output::*;
+#[cfg(unix)]
+pub use pty::*;
+pub use repeat::*;
+pub use rlimit::*;
+pub use rusage::*;
+pub use signal::Signal;
+pub use system_log::*;
+pub use tail::*;
 pub use tokio_impl::*;
 
-#[derive(derive_new::new, Debug, Clone, PartialEq, derive_getters::Getters)]
-pub struct Exit {
-    code: i32,
+/// Settings applied to the child process between fork and exec.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpawnOptions {
+    pub limits: ResourceLimits,
+    #[cfg(unix)]
+    pub credentials: Option<Credentials>,
+    /// run the child in a process group of its own, signalled instead of the child
+    #[cfg(unix)]
+    pub process_group: bool,
+    /// kill the child, along with its process group, when the future running it is dropped
+    pub kill_on_drop: bool,
+}
+
+impl SpawnOptions {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    #[cfg(unix)]
+    fn apply(&self) -> std::io::Result<()> {
+        self.limits.apply()?;
+
+        if self.process_group && unsafe { libc::setpgid(0, 0) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        match &self.credentials {
+            Some(credentials) => credentials.apply(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// How a child process terminated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Exit {
+    /// exited normally with the code
+    Code(i32),
+    /// killed by the signal
+    Signal { signal: i32, core_dumped: bool },
+}
+
+impl Exit {
+    pub fn success(&self) -> bool {
+        matches!(self, Exit::Code(0))
+    }
+
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            Exit::Code(code) => Some(*code),
+            Exit::Signal { .. } => None,
+        }
+    }
+
+    /// Name of the signal that killed the child, such as `SIGKILL`.
+    pub fn signal_name(&self) -> Option<String> {
+        match self {
+            Exit::Signal { signal, .. } => Some(signal::name(*signal)),
+            Exit::Code(_) => None,
+        }
+    }
+
+    pub fn core_dumped(&self) -> bool {
+        matches!(
+            self,
+            Exit::Signal {
+                core_dumped: true,
+                ..
+            }
+        )
+    }
+
+    /// Exit status as shells report it: the code, or 128 plus the number of the signal.
+    pub fn status(&self) -> i32 {
+        match self {
+            Exit::Code(code) => *code,
+            Exit::Signal { signal, .. } => 128 + signal,
+        }
+    }
+
+    /// Whether the child was killed for exceeding a resource limit.
+    pub fn limit_exceeded(&self) -> bool {
+        match self {
+            Exit::Signal { signal, .. } => signal::is_limit_exceeded(*signal),
+            Exit::Code(_) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Exit::Code(code) => write!(f, "exited with code {}", code),
+            Exit::Signal {
+                signal,
+                core_dumped,
+            } => {
+                write!(f, "killed by {}", signal::name(*signal))?;
+                if *core_dumped {
+                    write!(f, " (core dumped)")?;
+                }
+                if self.limit_exceeded() {
+                    write!(f, " (resource limit exceeded)")?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 #[async_trait::async_trait]
 pub trait PipedCmdExecute {
-    async fn piped_exec(&self, command: &str) -> std::io::Result<Exit>;
+    async fn piped_exec(&self, command: &str, attempt: usize) -> std::io::Result<Exit>;
+}
+
+/// How a command run with a timeout exited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedExit {
+    pub exit: Exit,
+    /// whether the command was signalled after running into the timeout
+    pub timed_out: bool,
+}
+
+#[async_trait::async_trait]
+pub trait TimedCmdExecute {
+    async fn timed_exec(&self, command: &str, timeout: &Timeout) -> std::io::Result<TimedExit>;
 }
 
 #[async_trait::async_trait]
 pub trait Sleep {
     async fn sleep_sec(&self, sec: f64);
 }
+
+pub trait Clock {
+    fn now(&self) -> chrono::DateTime<chrono::Utc>;
+}
+
+/// Name of this host, as recorded in lock files and sent to the system log. `-`, the nil value
+/// of syslog, when it cannot be determined.
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+
+    match unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } {
+        0 => {
+            let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+            String::from_utf8_lossy(&buf[..len]).into_owned()
+        }
+        _ => "-".to_string(),
+    }
+}
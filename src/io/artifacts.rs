@@ -0,0 +1,202 @@
+use super::*;
+use std::path::PathBuf;
+
+/// Directory receiving `attempt-<n>.stdout`, `attempt-<n>.stderr` and `attempt-<n>.json` for
+/// every attempt, or only for those that failed. The output is written by the `ArtifactFiles`
+/// sink, the JSON by the history once the attempt has been recorded.
+#[derive(Debug, Clone)]
+pub struct Artifacts {
+    dir: PathBuf,
+    failures_only: bool,
+}
+
+impl Artifacts {
+    /// Creates the directory if it does not exist.
+    pub fn create(dir: PathBuf) -> std::io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            failures_only: false,
+        })
+    }
+
+    /// Removes the files of attempts that succeeded.
+    pub fn failures_only(self) -> Self {
+        Self {
+            failures_only: true,
+            ..self
+        }
+    }
+
+    fn path(&self, attempt: usize, extension: &str) -> PathBuf {
+        self.dir.join(format!("attempt-{}.{}", attempt, extension))
+    }
+
+    pub fn files(&self) -> ArtifactFiles {
+        ArtifactFiles {
+            artifacts: self.clone(),
+            streams: None,
+        }
+    }
+
+    /// Writes how the attempt ended, including attempts that could not be spawned and so have
+    /// no output.
+    pub fn record(&self, attempt: &Attempt) -> std::io::Result<()> {
+        if self.failures_only && attempt.succeeded() {
+            return Ok(());
+        }
+
+        let json = serde_json::to_vec_pretty(&attempt.report()).map_err(std::io::Error::from)?;
+        std::fs::write(self.path(attempt.number, "json"), json)
+    }
+}
+
+struct Streams {
+    attempt: usize,
+    files: [std::io::BufWriter<std::fs::File>; 2],
+}
+
+/// Writes the output of each attempt into the `Artifacts` directory.
+pub struct ArtifactFiles {
+    artifacts: Artifacts,
+    streams: Option<Streams>,
+}
+
+impl OutputSink for ArtifactFiles {
+    fn begin(&mut self, attempt: usize, _: Option<u32>) -> std::io::Result<()> {
+        let create = |stream: Stream| -> std::io::Result<_> {
+            let path = self.artifacts.path(attempt, &stream.to_string());
+            Ok(std::io::BufWriter::new(std::fs::File::create(path)?))
+        };
+
+        self.streams = Some(Streams {
+            attempt,
+            files: [create(Stream::Stdout)?, create(Stream::Stderr)?],
+        });
+
+        Ok(())
+    }
+
+    fn write(&mut self, stream: Stream, data: &[u8]) -> std::io::Result<()> {
+        use std::io::Write;
+
+        match &mut self.streams {
+            Some(streams) => streams.files[stream as usize].write_all(data),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(&mut self, exit: &Exit) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut streams = match self.streams.take() {
+            Some(streams) => streams,
+            None => return Ok(()),
+        };
+
+        for file in streams.files.iter_mut() {
+            file.flush()?;
+        }
+
+        if self.artifacts.failures_only && exit.success() {
+            for stream in [Stream::Stdout, Stream::Stderr] {
+                std::fs::remove_file(self.artifacts.path(streams.attempt, &stream.to_string()))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attempt(number: usize, result: Result<Exit, String>) -> Attempt {
+        Attempt {
+            number,
+            command: "x".into(),
+            started: chrono::Local::now(),
+            duration: Default::default(),
+            result,
+        }
+    }
+
+    fn names(dir: &std::path::Path) -> Vec<String> {
+        let mut names = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn write_files_per_attempt() {
+        let dir = tempfile::tempdir().unwrap();
+        let artifacts = Artifacts::create(dir.path().into()).unwrap();
+        let mut files = artifacts.files();
+
+        for number in 1..=2 {
+            files.begin(number, Some(42)).unwrap();
+            files.write(Stream::Stdout, b"out\n").unwrap();
+            files.write(Stream::Stderr, b"err\n").unwrap();
+            files.finish(&Exit::Code(number as i32)).unwrap();
+            artifacts
+                .record(&attempt(number, Ok(Exit::Code(number as i32))))
+                .unwrap();
+        }
+
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("attempt-1.stdout"), "out\n");
+        assert_eq!(read("attempt-2.stderr"), "err\n");
+
+        let metadata = serde_json::from_str::<serde_json::Value>(&read("attempt-2.json")).unwrap();
+        assert_eq!(metadata["attempt"], 2);
+        assert_eq!(metadata["command"], "x");
+        assert_eq!(metadata["code"], 2);
+        assert_eq!(metadata["signal"], serde_json::Value::Null);
+        assert!(metadata["duration_ms"].is_u64());
+    }
+
+    #[test]
+    fn keep_files_of_failures_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let artifacts = Artifacts::create(dir.path().into())
+            .unwrap()
+            .failures_only();
+        let mut files = artifacts.files();
+
+        for number in 0..=1 {
+            files.begin(number, None).unwrap();
+            files.write(Stream::Stdout, b"out\n").unwrap();
+            files.finish(&Exit::Code(number as i32)).unwrap();
+            artifacts
+                .record(&attempt(number, Ok(Exit::Code(number as i32))))
+                .unwrap();
+        }
+
+        assert_eq!(
+            names(dir.path()),
+            ["attempt-1.json", "attempt-1.stderr", "attempt-1.stdout"]
+        );
+    }
+
+    #[test]
+    fn describe_attempts_that_failed_to_spawn() {
+        let dir = tempfile::tempdir().unwrap();
+        let artifacts = Artifacts::create(dir.path().into())
+            .unwrap()
+            .failures_only();
+
+        artifacts
+            .record(&attempt(1, Err("command not found 'x'".into())))
+            .unwrap();
+
+        assert_eq!(names(dir.path()), ["attempt-1.json"]);
+        let json = std::fs::read_to_string(dir.path().join("attempt-1.json")).unwrap();
+        let metadata = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert_eq!(metadata["error"], "command not found 'x'");
+        assert_eq!(metadata["code"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,59 @@
+use super::*;
+use std::time::Duration;
+
+/// What happened to a single attempt.
+#[derive(Debug, Clone)]
+pub struct Attempt {
+    pub number: usize,
+    /// the command run, which differs between the alternatives of fallback
+    pub command: String,
+    pub started: chrono::DateTime<chrono::Local>,
+    pub duration: Duration,
+    /// how the child exited, or why it could not be spawned
+    pub result: Result<Exit, String>,
+}
+
+impl Attempt {
+    /// How the attempt ended, as shown to the user.
+    pub fn reason(&self) -> String {
+        match &self.result {
+            Ok(exit) => exit.to_string(),
+            Err(error) => error.clone(),
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        matches!(&self.result, Ok(exit) if exit.success())
+    }
+
+    /// The attempt as written to the JSON summary, the event log and the artifacts.
+    pub fn report(&self) -> AttemptReport<'_> {
+        let exit = self.result.as_ref().ok();
+
+        AttemptReport {
+            attempt: self.number,
+            command: &self.command,
+            started: self
+                .started
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, false),
+            duration_ms: self.duration.as_millis(),
+            code: exit.and_then(Exit::code),
+            signal: exit.and_then(Exit::signal_name),
+            core_dumped: exit.map_or(false, Exit::core_dumped),
+            error: self.result.as_ref().err().map(String::as_str),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct AttemptReport<'a> {
+    attempt: usize,
+    command: &'a str,
+    started: String,
+    duration_ms: u128,
+    code: Option<i32>,
+    signal: Option<String>,
+    core_dumped: bool,
+    /// why the command could not be spawned
+    error: Option<&'a str>,
+}
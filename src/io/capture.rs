@@ -0,0 +1,244 @@
+use super::*;
+use std::io::{Read, Seek, Write};
+
+/// Which attempts have their output shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputMode {
+    /// stream the output of every attempt as it is produced
+    All,
+    /// show only the output of the last attempt
+    Final,
+    /// show only the output of failed attempts
+    Failures,
+    /// discard every output
+    None,
+}
+
+impl std::str::FromStr for OutputMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(OutputMode::All),
+            "final" => Ok(OutputMode::Final),
+            "failures" => Ok(OutputMode::Failures),
+            "none" => Ok(OutputMode::None),
+            _ => Err(format!("invalid output mode '{}'", s)),
+        }
+    }
+}
+
+const SPILL_THRESHOLD: usize = 1 << 20;
+
+enum Storage {
+    Memory(Vec<u8>),
+    File(std::fs::File),
+}
+
+/// Records the output of an attempt, keeping the order of stdout and stderr. Data beyond
+/// `threshold` bytes is spilled to an anonymous temporary file.
+pub struct Spool {
+    storage: Storage,
+    threshold: usize,
+}
+
+impl Spool {
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            storage: Storage::Memory(Vec::new()),
+            threshold,
+        }
+    }
+
+    pub fn write(&mut self, stream: Stream, data: &[u8]) -> std::io::Result<()> {
+        let mut record = Vec::with_capacity(data.len() + 5);
+        record.push(stream as u8);
+        record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        record.extend_from_slice(data);
+
+        match &mut self.storage {
+            Storage::Memory(buf) if buf.len() + record.len() <= self.threshold => {
+                buf.extend_from_slice(&record);
+            }
+            Storage::Memory(buf) => {
+                let mut file = tempfile::tempfile()?;
+                file.write_all(buf)?;
+                file.write_all(&record)?;
+                self.storage = Storage::File(file);
+            }
+            Storage::File(file) => file.write_all(&record)?,
+        }
+
+        Ok(())
+    }
+
+    /// Passes the recorded output to the sink in the original order.
+    pub fn replay(self, sink: &mut dyn OutputSink) -> std::io::Result<()> {
+        let mut reader: Box<dyn Read> = match self.storage {
+            Storage::Memory(buf) => Box::new(std::io::Cursor::new(buf)),
+            Storage::File(mut file) => {
+                file.rewind()?;
+                Box::new(std::io::BufReader::new(file))
+            }
+        };
+
+        let mut header = [0u8; 5];
+        let mut data = Vec::new();
+
+        loop {
+            match reader.read_exact(&mut header) {
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                result => result?,
+            }
+
+            let stream = match header[0] {
+                0 => Stream::Stdout,
+                _ => Stream::Stderr,
+            };
+            let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]);
+
+            data.resize(len as usize, 0);
+            reader.read_exact(&mut data)?;
+            sink.write(stream, &data)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Holds back the output of each attempt and decides whether to pass it to the inner sink once
+/// the attempt has exited.
+pub struct Captured<S> {
+    inner: S,
+    mode: OutputMode,
+    current: Option<Spool>,
+    last: Option<Spool>,
+}
+
+impl<S: OutputSink> Captured<S> {
+    pub fn new(inner: S, mode: OutputMode) -> Self {
+        Self {
+            inner,
+            mode,
+            current: None,
+            last: None,
+        }
+    }
+}
+
+impl<S: OutputSink> OutputSink for Captured<S> {
+    fn begin(&mut self, attempt: usize, pid: Option<u32>) -> std::io::Result<()> {
+        self.last = None;
+        self.current = match self.mode {
+            OutputMode::Final | OutputMode::Failures => Some(Spool::new(SPILL_THRESHOLD)),
+            OutputMode::All | OutputMode::None => None,
+        };
+        self.inner.begin(attempt, pid)
+    }
+
+    fn write(&mut self, stream: Stream, data: &[u8]) -> std::io::Result<()> {
+        match (self.mode, &mut self.current) {
+            (OutputMode::All, _) => self.inner.write(stream, data),
+            (OutputMode::None, _) => Ok(()),
+            (_, Some(spool)) => spool.write(stream, data),
+            (_, None) => Ok(()),
+        }
+    }
+
+    fn finish(&mut self, exit: &Exit) -> std::io::Result<()> {
+        let spool = self.current.take();
+
+        match (self.mode, spool) {
+            (OutputMode::Failures, Some(spool)) if !exit.success() => {
+                spool.replay(&mut self.inner)?
+            }
+            (OutputMode::Final, spool) => self.last = spool,
+            _ => (),
+        }
+
+        self.inner.finish(exit)
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        if let Some(spool) = self.last.take() {
+            spool.replay(&mut self.inner)?;
+        }
+
+        self.inner.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Collected = std::sync::Arc<std::sync::Mutex<Vec<u8>>>;
+
+    struct Collect(Collected);
+
+    impl OutputSink for Collect {
+        fn write(&mut self, _: Stream, data: &[u8]) -> std::io::Result<()> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    fn run(mode: OutputMode, exits: &[Exit]) -> String {
+        let collected = Collected::default();
+        let mut sink = Captured::new(Collect(collected.clone()), mode);
+
+        for (i, exit) in exits.iter().enumerate() {
+            sink.begin(i + 1, None).unwrap();
+            sink.write(Stream::Stdout, format!("#{}\n", i + 1).as_bytes())
+                .unwrap();
+            sink.finish(exit).unwrap();
+        }
+        sink.close().unwrap();
+
+        let collected = collected.lock().unwrap();
+        String::from_utf8(collected.clone()).unwrap()
+    }
+
+    #[test]
+    fn show_all_attempts() {
+        let actual = run(OutputMode::All, &[Exit::Code(1), Exit::Code(0)]);
+        assert_eq!(actual, "#1\n#2\n");
+    }
+
+    #[test]
+    fn show_final_attempt() {
+        let actual = run(
+            OutputMode::Final,
+            &[Exit::Code(1), Exit::Code(1), Exit::Code(0)],
+        );
+        assert_eq!(actual, "#3\n");
+    }
+
+    #[test]
+    fn show_failed_attempts() {
+        let actual = run(
+            OutputMode::Failures,
+            &[Exit::Code(1), Exit::Code(0), Exit::Code(2)],
+        );
+        assert_eq!(actual, "#1\n#3\n");
+    }
+
+    #[test]
+    fn show_nothing() {
+        let actual = run(OutputMode::None, &[Exit::Code(1), Exit::Code(0)]);
+        assert_eq!(actual, "");
+    }
+
+    #[test]
+    fn replay_spilled_output_in_order() {
+        let collected = Collected::default();
+        let mut spool = Spool::new(8);
+
+        spool.write(Stream::Stdout, b"abc").unwrap();
+        spool.write(Stream::Stderr, b"defghijk").unwrap();
+        spool.write(Stream::Stdout, b"l").unwrap();
+        spool.replay(&mut Collect(collected.clone())).unwrap();
+
+        assert_eq!(*collected.lock().unwrap(), b"abcdefghijkl");
+    }
+}
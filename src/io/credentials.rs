@@ -0,0 +1,197 @@
+use std::ffi::{CStr, CString};
+
+/// User and group identity the child process switches to before exec.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    /// supplementary groups, prepared for `setgroups(2)` since nothing may be allocated between
+    /// fork and exec
+    pub groups: Vec<libc::gid_t>,
+}
+
+struct User {
+    /// None for a numeric uid without a passwd entry
+    name: Option<CString>,
+    uid: u32,
+    gid: u32,
+}
+
+/// Calls a reentrant `get*_r` lookup, growing the buffer while it is too small. None when there
+/// is no entry.
+fn lookup<T>(
+    mut call: impl FnMut(&mut T, &mut Vec<libc::c_char>, &mut *mut T) -> libc::c_int,
+) -> std::io::Result<Option<T>> {
+    let mut entry: T = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0; 1024];
+
+    loop {
+        let mut result = std::ptr::null_mut();
+
+        match call(&mut entry, &mut buf, &mut result) {
+            0 if result.is_null() => return Ok(None),
+            0 => return Ok(Some(entry)),
+            libc::ERANGE => buf.resize(buf.len() * 2, 0),
+            code => return Err(std::io::Error::from_raw_os_error(code)),
+        }
+    }
+}
+
+// uid_t and gid_t are signed on some platforms
+#[allow(clippy::unnecessary_cast)]
+fn lookup_user(user: &str) -> anyhow::Result<User> {
+    let uid = user.parse::<u32>();
+    let passwd = match uid {
+        Ok(uid) => lookup(|entry: &mut libc::passwd, buf, result| unsafe {
+            libc::getpwuid_r(uid as _, entry, buf.as_mut_ptr(), buf.len(), result)
+        })?,
+        Err(_) => {
+            let name = CString::new(user)?;
+            lookup(|entry: &mut libc::passwd, buf, result| unsafe {
+                libc::getpwnam_r(name.as_ptr(), entry, buf.as_mut_ptr(), buf.len(), result)
+            })?
+        }
+    };
+
+    match (passwd, uid) {
+        (Some(passwd), _) => Ok(User {
+            name: Some(unsafe { CStr::from_ptr(passwd.pw_name) }.to_owned()),
+            uid: passwd.pw_uid as u32,
+            gid: passwd.pw_gid as u32,
+        }),
+        // Arbitrary uids, as common in containers, run with the group of the same id.
+        (None, Ok(uid)) => Ok(User {
+            name: None,
+            uid,
+            gid: uid,
+        }),
+        (None, Err(_)) => anyhow::bail!("unknown user '{}'", user),
+    }
+}
+
+// gid_t is signed on some platforms
+#[allow(clippy::unnecessary_cast)]
+fn lookup_group(group: &str) -> anyhow::Result<u32> {
+    if let Ok(gid) = group.parse::<u32>() {
+        return Ok(gid);
+    }
+
+    let name = CString::new(group)?;
+    let entry = lookup(|entry: &mut libc::group, buf, result| unsafe {
+        libc::getgrnam_r(name.as_ptr(), entry, buf.as_mut_ptr(), buf.len(), result)
+    })?;
+
+    match entry {
+        Some(entry) => Ok(entry.gr_gid as u32),
+        None => anyhow::bail!("unknown group '{}'", group),
+    }
+}
+
+fn group_list(user: &CStr, gid: libc::gid_t) -> Vec<libc::gid_t> {
+    let mut count: libc::c_int = 32;
+
+    loop {
+        let mut groups = vec![0; count as usize];
+        let capacity = count;
+
+        let result =
+            unsafe { libc::getgrouplist(user.as_ptr(), gid as _, groups.as_mut_ptr(), &mut count) };
+
+        if result >= 0 {
+            groups.truncate(count as usize);
+            return groups;
+        }
+
+        count = std::cmp::max(count, capacity * 2);
+    }
+}
+
+impl Credentials {
+    /// Resolves names or numeric ids. The supplementary groups of the user are initialised
+    /// from the group database, as `initgroups(3)` would do, and left empty for a uid without
+    /// a passwd entry.
+    pub fn resolve(user: Option<&str>, group: Option<&str>) -> anyhow::Result<Self> {
+        let group = group.map(lookup_group).transpose()?;
+
+        match (user.map(lookup_user).transpose()?, group) {
+            (Some(user), group) => {
+                let gid = group.unwrap_or(user.gid);
+                Ok(Self {
+                    uid: user.uid,
+                    gid,
+                    groups: user
+                        .name
+                        .map_or(vec![], |name| group_list(&name, gid as libc::gid_t)),
+                })
+            }
+            (None, Some(gid)) => Ok(Self {
+                uid: unsafe { libc::getuid() } as u32,
+                gid,
+                groups: vec![gid as libc::gid_t],
+            }),
+            (None, None) => anyhow::bail!("neither user nor group is specified"),
+        }
+    }
+
+    /// Whether the current process is allowed to switch to arbitrary credentials.
+    pub fn privileged() -> bool {
+        unsafe { libc::geteuid() == 0 }
+    }
+
+    /// Switches the calling process to the credentials. Intended to be called between fork and
+    /// exec, it only makes system calls on the data prepared by `resolve`.
+    pub(super) fn apply(&self) -> std::io::Result<()> {
+        if unsafe { libc::setgroups(self.groups.len() as _, self.groups.as_ptr()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        if unsafe { libc::setgid(self.gid as libc::gid_t) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        if unsafe { libc::setuid(self.uid as libc::uid_t) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_user_by_name() {
+        let actual = Credentials::resolve(Some("root"), None).unwrap();
+        assert_eq!(actual.uid, 0);
+        assert_eq!(actual.gid, 0);
+        assert!(actual.groups.contains(&0));
+    }
+
+    #[test]
+    fn resolve_group_by_id() {
+        let actual = Credentials::resolve(Some("0"), Some("12345")).unwrap();
+        assert_eq!(actual.uid, 0);
+        assert_eq!(actual.gid, 12345);
+    }
+
+    #[test]
+    fn resolve_uid_without_passwd_entry() {
+        let actual = Credentials::resolve(Some("987654"), None).unwrap();
+        assert_eq!(
+            actual,
+            Credentials {
+                uid: 987654,
+                gid: 987654,
+                groups: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn resolve_unknown_user() {
+        let actual = Credentials::resolve(Some("cx-unknown-user"), None);
+        assert!(actual.is_err());
+    }
+}
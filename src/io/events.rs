@@ -0,0 +1,215 @@
+use super::*;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Version of the event schema. Bump it whenever a field is renamed or removed.
+pub const EVENTS_VERSION: u32 = 1;
+
+/// Where events are written: `json` (stderr) or `json:<path>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventsTarget {
+    pub path: Option<PathBuf>,
+}
+
+impl std::str::FromStr for EventsTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "json" => Ok(Self { path: None }),
+            Some(("json", path)) if !path.is_empty() => Ok(Self {
+                path: Some(path.into()),
+            }),
+            _ => Err(format!("invalid events target '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Event<'a> {
+    AttemptStarted {
+        attempt: usize,
+        /// missing when the command could not be spawned
+        pid: Option<u32>,
+    },
+    AttemptExited(AttemptReport<'a>),
+    Sleeping {
+        delay_sec: f64,
+    },
+    GaveUp {
+        attempts: usize,
+    },
+    Succeeded {
+        attempts: usize,
+    },
+    Stopped {
+        attempts: usize,
+    },
+}
+
+#[derive(serde::Serialize)]
+struct Record<'a> {
+    version: u32,
+    ts: String,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Writes the lifecycle of the state machine as JSON lines.
+pub struct EventLog {
+    writer: Mutex<Box<dyn std::io::Write + Send>>,
+}
+
+impl std::fmt::Debug for EventLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventLog").finish_non_exhaustive()
+    }
+}
+
+impl EventLog {
+    pub fn new(writer: Box<dyn std::io::Write + Send>) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    pub fn open(target: &EventsTarget) -> std::io::Result<Self> {
+        let writer: Box<dyn std::io::Write + Send> = match &target.path {
+            Some(path) => Box::new(
+                std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?,
+            ),
+            None => Box::new(std::io::stderr()),
+        };
+
+        Ok(Self::new(writer))
+    }
+
+    fn emit(&self, event: Event) {
+        let record = Record {
+            version: EVENTS_VERSION,
+            ts: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            event: &event,
+        };
+
+        if let Ok(mut line) = serde_json::to_vec(&record) {
+            line.push(b'\n');
+            let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+            let _ = writer.write_all(&line).and_then(|_| writer.flush());
+        }
+    }
+
+    pub fn attempt_started(&self, attempt: usize, pid: Option<u32>) {
+        self.emit(Event::AttemptStarted { attempt, pid })
+    }
+
+    pub fn attempt_exited(&self, attempt: &Attempt) {
+        self.emit(Event::AttemptExited(attempt.report()))
+    }
+
+    pub fn sleeping(&self, sec: f64) {
+        self.emit(Event::Sleeping { delay_sec: sec })
+    }
+
+    pub fn gave_up(&self, attempts: usize) {
+        self.emit(Event::GaveUp { attempts })
+    }
+
+    pub fn succeeded(&self, attempts: usize) {
+        self.emit(Event::Succeeded { attempts })
+    }
+
+    /// The run ended without success or failure, as supervise, cron, bench and flaky do.
+    pub fn stopped(&self, attempts: usize) {
+        self.emit(Event::Stopped { attempts })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn lines(&self) -> Vec<serde_json::Value> {
+            let buf = self.0.lock().unwrap();
+            String::from_utf8(buf.clone())
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        }
+    }
+
+    #[test]
+    fn parse_events_target() {
+        assert_eq!("json".parse(), Ok(EventsTarget { path: None }));
+        assert_eq!(
+            "json:/tmp/events.jsonl".parse(),
+            Ok(EventsTarget {
+                path: Some("/tmp/events.jsonl".into())
+            })
+        );
+        assert!("yaml".parse::<EventsTarget>().is_err());
+        assert!("json:".parse::<EventsTarget>().is_err());
+    }
+
+    fn attempt(number: usize, millis: u64, result: Result<Exit, String>) -> Attempt {
+        Attempt {
+            number,
+            command: "x".into(),
+            started: chrono::Local::now(),
+            duration: std::time::Duration::from_millis(millis),
+            result,
+        }
+    }
+
+    #[test]
+    fn emit_attempt_events() {
+        let buffer = Buffer::default();
+        let events = EventLog::new(Box::new(buffer.clone()));
+
+        events.attempt_started(1, Some(42));
+        events.attempt_exited(&attempt(1, 5, Ok(Exit::Code(3))));
+        events.sleeping(0.5);
+        events.attempt_started(2, None);
+        events.attempt_exited(&attempt(2, 0, Err("command not found 'x'".into())));
+        events.gave_up(2);
+
+        let lines = buffer.lines();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0]["version"], EVENTS_VERSION);
+        assert_eq!(lines[0]["event"], "attempt_started");
+        assert_eq!(lines[0]["attempt"], 1);
+        assert_eq!(lines[0]["pid"], 42);
+        assert_eq!(lines[1]["event"], "attempt_exited");
+        assert_eq!(lines[1]["attempt"], 1);
+        assert_eq!(lines[1]["command"], "x");
+        assert_eq!(lines[1]["code"], 3);
+        assert_eq!(lines[1]["signal"], serde_json::Value::Null);
+        assert_eq!(lines[1]["error"], serde_json::Value::Null);
+        assert_eq!(lines[1]["duration_ms"], 5);
+        assert_eq!(lines[2]["event"], "sleeping");
+        assert_eq!(lines[2]["delay_sec"], 0.5);
+        assert_eq!(lines[4]["code"], serde_json::Value::Null);
+        assert_eq!(lines[4]["error"], "command not found 'x'");
+        assert_eq!(lines[5]["event"], "gave_up");
+        assert_eq!(lines[5]["attempts"], 2);
+    }
+}
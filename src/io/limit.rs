@@ -0,0 +1,332 @@
+use super::*;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+/// What happens to the output beyond the limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LimitPolicy {
+    /// discard the rest of the output
+    Truncate,
+    /// terminate the child, failing the attempt
+    Kill,
+    /// keep the head and the tail of the output, discarding the middle
+    DropMiddle,
+}
+
+impl std::str::FromStr for LimitPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "truncate" => Ok(LimitPolicy::Truncate),
+            "kill" => Ok(LimitPolicy::Kill),
+            "drop-middle" => Ok(LimitPolicy::DropMiddle),
+            _ => Err(format!("invalid output limit policy '{}'", s)),
+        }
+    }
+}
+
+/// Maximum number of bytes passed to the sinks, counted over both streams.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputLimit {
+    pub per_attempt: Option<u64>,
+    pub per_run: Option<u64>,
+    pub policy: LimitPolicy,
+}
+
+impl Default for OutputLimit {
+    fn default() -> Self {
+        Self {
+            per_attempt: None,
+            per_run: None,
+            policy: LimitPolicy::Truncate,
+        }
+    }
+}
+
+/// Part of a write that may be passed to the sinks.
+pub(super) struct Admitted<'d> {
+    pub data: &'d [u8],
+    pub marker: Option<Cow<'static, [u8]>>,
+    /// whether the child should be terminated
+    pub kill: bool,
+}
+
+/// Counts the bytes of each attempt and of the whole run, and applies the `LimitPolicy` once
+/// the budget of the attempt is used up. The budget of an attempt is the smaller of the
+/// per-attempt limit and what is left of the per-run limit.
+pub(super) struct Limiter {
+    limit: OutputLimit,
+    run_passed: u64,
+    budget: Option<u64>,
+    passed: u64,
+    exceeded: bool,
+    tail: VecDeque<(Stream, Vec<u8>)>,
+    tail_len: u64,
+    dropped: u64,
+    at_line_start: [bool; 2],
+}
+
+impl Limiter {
+    pub fn new(limit: OutputLimit) -> Self {
+        Self {
+            limit,
+            run_passed: 0,
+            budget: None,
+            passed: 0,
+            exceeded: false,
+            tail: VecDeque::new(),
+            tail_len: 0,
+            dropped: 0,
+            at_line_start: [true; 2],
+        }
+    }
+
+    pub fn begin(&mut self) {
+        let run_left = self
+            .limit
+            .per_run
+            .map(|max| max.saturating_sub(self.run_passed));
+
+        self.budget = match (self.limit.per_attempt, run_left) {
+            (Some(attempt), Some(run)) => Some(std::cmp::min(attempt, run)),
+            (attempt, run) => attempt.or(run),
+        };
+        self.passed = 0;
+        self.exceeded = false;
+        self.tail.clear();
+        self.tail_len = 0;
+        self.dropped = 0;
+        self.at_line_start = [true; 2];
+    }
+
+    /// Bytes passed before the rest is truncated, or kept from the start with `DropMiddle`.
+    fn head(&self, budget: u64) -> u64 {
+        match self.limit.policy {
+            LimitPolicy::DropMiddle => budget - budget / 2,
+            LimitPolicy::Truncate | LimitPolicy::Kill => budget,
+        }
+    }
+
+    fn marker(&self, stream: Stream, text: String) -> Cow<'static, [u8]> {
+        let newline = match self.at_line_start[stream as usize] {
+            true => "",
+            false => "\n",
+        };
+        format!("{}[cx: {}]\n", newline, text).into_bytes().into()
+    }
+
+    pub fn admit<'d>(&mut self, stream: Stream, data: &'d [u8]) -> Admitted<'d> {
+        let budget = match self.budget {
+            Some(budget) => budget,
+            None => {
+                return Admitted {
+                    data,
+                    marker: None,
+                    kill: false,
+                }
+            }
+        };
+
+        let room = self.head(budget).saturating_sub(self.passed);
+        let len = std::cmp::min(room, data.len() as u64) as usize;
+        let (head, rest) = data.split_at(len);
+
+        self.passed += len as u64;
+        self.run_passed += len as u64;
+        if let Some(last) = head.last() {
+            self.at_line_start[stream as usize] = *last == b'\n';
+        }
+
+        if rest.is_empty() {
+            return Admitted {
+                data: head,
+                marker: None,
+                kill: false,
+            };
+        }
+
+        let first = !self.exceeded;
+        self.exceeded = true;
+
+        let marker = match self.limit.policy {
+            LimitPolicy::Truncate if first => Some(self.marker(
+                stream,
+                format!("output truncated after {} bytes", self.passed),
+            )),
+            LimitPolicy::Kill if first => Some(self.marker(
+                stream,
+                format!(
+                    "output exceeded {} bytes, terminating the child",
+                    self.passed
+                ),
+            )),
+            LimitPolicy::DropMiddle => {
+                self.keep_tail(stream, rest, budget / 2);
+                None
+            }
+            _ => None,
+        };
+
+        Admitted {
+            data: head,
+            kill: first && self.limit.policy == LimitPolicy::Kill,
+            marker,
+        }
+    }
+
+    fn keep_tail(&mut self, stream: Stream, data: &[u8], capacity: u64) {
+        self.tail.push_back((stream, data.to_vec()));
+        self.tail_len += data.len() as u64;
+
+        while self.tail_len > capacity {
+            let excess = self.tail_len - capacity;
+            let front = match self.tail.front_mut() {
+                Some((_, front)) => front,
+                None => break,
+            };
+
+            if front.len() as u64 <= excess {
+                self.tail_len -= front.len() as u64;
+                self.dropped += front.len() as u64;
+                self.tail.pop_front();
+            } else {
+                front.drain(..excess as usize);
+                self.tail_len -= excess;
+                self.dropped += excess;
+            }
+        }
+    }
+
+    /// Output held back until the attempt has exited: the marker and the tail of `DropMiddle`.
+    pub fn finish(&mut self) -> Vec<(Stream, Cow<'static, [u8]>)> {
+        let mut held = Vec::new();
+
+        if self.tail.is_empty() && self.dropped == 0 {
+            return held;
+        }
+
+        if self.dropped > 0 {
+            let stream = self.tail.front().map_or(Stream::Stdout, |(s, _)| *s);
+            let text = format!("{} bytes dropped", self.dropped);
+            held.push((stream, self.marker(stream, text)));
+        }
+
+        for (stream, data) in self.tail.drain(..) {
+            self.run_passed += data.len() as u64;
+            held.push((stream, data.into()));
+        }
+        self.tail_len = 0;
+        self.dropped = 0;
+
+        held
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(per_attempt: Option<u64>, per_run: Option<u64>, policy: LimitPolicy) -> Limiter {
+        Limiter::new(OutputLimit {
+            per_attempt,
+            per_run,
+            policy,
+        })
+    }
+
+    fn collect(limiter: &mut Limiter, writes: &[&[u8]]) -> (Vec<u8>, bool) {
+        let mut out = Vec::new();
+        let mut kill = false;
+
+        for data in writes {
+            let admitted = limiter.admit(Stream::Stdout, data);
+            out.extend_from_slice(admitted.data);
+            if let Some(marker) = admitted.marker {
+                out.extend_from_slice(&marker);
+            }
+            kill |= admitted.kill;
+        }
+
+        for (_, data) in limiter.finish() {
+            out.extend_from_slice(&data);
+        }
+
+        (out, kill)
+    }
+
+    #[test]
+    fn parse_limit_policy() {
+        assert_eq!("truncate".parse(), Ok(LimitPolicy::Truncate));
+        assert_eq!("kill".parse(), Ok(LimitPolicy::Kill));
+        assert_eq!("drop-middle".parse(), Ok(LimitPolicy::DropMiddle));
+        assert!("ignore".parse::<LimitPolicy>().is_err());
+    }
+
+    #[test]
+    fn pass_through_without_limit() {
+        let mut limiter = limiter(None, None, LimitPolicy::Kill);
+        limiter.begin();
+
+        let (out, kill) = collect(&mut limiter, &[b"abc\n", b"def\n"]);
+        assert_eq!(out, b"abc\ndef\n");
+        assert!(!kill);
+    }
+
+    #[test]
+    fn truncate_attempt_output() {
+        let mut limiter = limiter(Some(6), None, LimitPolicy::Truncate);
+        limiter.begin();
+
+        let (out, kill) = collect(&mut limiter, &[b"abc\n", b"def\n", b"ghi\n"]);
+        assert_eq!(out, b"abc\nde\n[cx: output truncated after 6 bytes]\n");
+        assert!(!kill);
+    }
+
+    #[test]
+    fn kill_on_excess() {
+        let mut limiter = limiter(Some(4), None, LimitPolicy::Kill);
+        limiter.begin();
+
+        let (out, kill) = collect(&mut limiter, &[b"abc\n", b"def\n", b"ghi\n"]);
+        assert_eq!(
+            out,
+            b"abc\n[cx: output exceeded 4 bytes, terminating the child]\n"
+        );
+        assert!(kill);
+    }
+
+    #[test]
+    fn keep_head_and_tail() {
+        let mut limiter = limiter(Some(8), None, LimitPolicy::DropMiddle);
+        limiter.begin();
+
+        let (out, _) = collect(&mut limiter, &[b"abc\n", b"def\n", b"ghi\n", b"jkl\n"]);
+        assert_eq!(out, b"abc\n[cx: 8 bytes dropped]\njkl\n");
+    }
+
+    #[test]
+    fn keep_everything_within_budget() {
+        let mut limiter = limiter(Some(8), None, LimitPolicy::DropMiddle);
+        limiter.begin();
+
+        let (out, _) = collect(&mut limiter, &[b"abc\n", b"def\n"]);
+        assert_eq!(out, b"abc\ndef\n");
+    }
+
+    #[test]
+    fn share_budget_over_run() {
+        let mut limiter = limiter(Some(6), Some(10), LimitPolicy::Truncate);
+
+        limiter.begin();
+        let (first, _) = collect(&mut limiter, &[b"abcdefgh\n"]);
+        limiter.begin();
+        let (second, _) = collect(&mut limiter, &[b"abcdefgh\n"]);
+        limiter.begin();
+        let (third, _) = collect(&mut limiter, &[b"abcdefgh\n"]);
+
+        assert_eq!(first, b"abcdef\n[cx: output truncated after 6 bytes]\n");
+        assert_eq!(second, b"abcd\n[cx: output truncated after 4 bytes]\n");
+        assert_eq!(third, b"[cx: output truncated after 0 bytes]\n");
+    }
+}
@@ -0,0 +1,146 @@
+use super::hostname;
+use std::io::{Seek, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::Duration;
+
+/// Exit code used when the lock is still held by someone else, EX_TEMPFAIL of sysexits.h.
+pub const LOCK_BUSY: i32 = 75;
+
+/// How often a busy lock is tried again while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Who holds a lock, as written into the lock file.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Holder {
+    pub pid: u32,
+    pub host: String,
+    pub since: String,
+    pub command: String,
+}
+
+impl std::fmt::Display for Holder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "pid {} on {} since {} ('{}')",
+            self.pid, self.host, self.since, self.command
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    /// still held by someone else when done waiting, by the holder if known
+    Busy(Option<Holder>),
+    Io(std::io::Error),
+}
+
+impl From<std::io::Error> for LockError {
+    fn from(e: std::io::Error) -> Self {
+        LockError::Io(e)
+    }
+}
+
+/// Exclusive `flock` on a file, held until it is dropped.
+#[derive(Debug)]
+pub struct FileLock {
+    file: std::fs::File,
+}
+
+impl FileLock {
+    /// Waits up to `wait` for the lock, or for as long as it takes when None.
+    pub async fn acquire(
+        path: &Path,
+        wait: Option<Duration>,
+        command: &str,
+    ) -> Result<Self, LockError> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(false)
+            .open(path)?;
+        let deadline = wait.map(|wait| tokio::time::Instant::now() + wait);
+
+        while !try_lock(&file)? {
+            match deadline {
+                Some(deadline) if tokio::time::Instant::now() >= deadline => {
+                    return Err(LockError::Busy(read_holder(path)));
+                }
+                _ => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        }
+
+        let holder = Holder {
+            pid: std::process::id(),
+            host: hostname(),
+            since: chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            command: command.to_string(),
+        };
+        file.set_len(0)?;
+        file.rewind()?;
+        serde_json::to_writer(&mut file, &holder).map_err(std::io::Error::from)?;
+        file.write_all(b"\n")?;
+
+        Ok(Self { file })
+    }
+
+    /// Clears close-on-exec, so that the command cx is replaced with keeps holding the lock.
+    /// Children started otherwise would hold it past the exit of cx.
+    pub fn inherit(&self) -> std::io::Result<()> {
+        let fd = self.file.as_raw_fd();
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+
+        if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+fn try_lock(file: &std::fs::File) -> std::io::Result<bool> {
+    match unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } {
+        0 => Ok(true),
+        _ => match std::io::Error::last_os_error() {
+            e if e.raw_os_error() == Some(libc::EWOULDBLOCK) => Ok(false),
+            e if e.kind() == std::io::ErrorKind::Interrupted => Ok(false),
+            e => Err(e),
+        },
+    }
+}
+
+fn read_holder(path: &Path) -> Option<Holder> {
+    let text = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn report_holder_of_busy_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("job.lock");
+
+        let lock = FileLock::acquire(&path, Some(Duration::ZERO), "first")
+            .await
+            .unwrap();
+
+        match FileLock::acquire(&path, Some(Duration::from_millis(150)), "second").await {
+            Err(LockError::Busy(Some(holder))) => {
+                assert_eq!(holder.pid, std::process::id());
+                assert_eq!(holder.command, "first");
+                assert_eq!(holder.host, hostname());
+            }
+            result => panic!("unexpected {:?}", result),
+        }
+
+        drop(lock);
+        assert!(FileLock::acquire(&path, Some(Duration::ZERO), "third")
+            .await
+            .is_ok());
+    }
+}
@@ -0,0 +1,289 @@
+use super::*;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RotatePeriod {
+    Hourly,
+    Daily,
+}
+
+impl std::str::FromStr for RotatePeriod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hourly" => Ok(RotatePeriod::Hourly),
+            "daily" => Ok(RotatePeriod::Daily),
+            _ => Err(format!("invalid rotation period '{}'", s)),
+        }
+    }
+}
+
+impl RotatePeriod {
+    fn key(&self, time: chrono::DateTime<chrono::Local>) -> String {
+        match self {
+            RotatePeriod::Hourly => time.format("%Y%m%d%H").to_string(),
+            RotatePeriod::Daily => time.format("%Y%m%d").to_string(),
+        }
+    }
+}
+
+/// When and how log files are rotated.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rotation {
+    pub max_size: Option<u64>,
+    pub period: Option<RotatePeriod>,
+    /// number of rotated files to keep
+    pub keep: usize,
+    /// compress rotated files with gzip
+    pub compress: bool,
+}
+
+/// Appends the output to a file, rotating it as `path.1`, `path.2`, ... (with `.gz` when
+/// compressed). Rotation only happens at line boundaries.
+pub struct LogFile {
+    path: PathBuf,
+    stream: Option<Stream>,
+    rotation: Rotation,
+    prefixer: LinePrefixer,
+    context: LineContext,
+    file: std::fs::File,
+    size: u64,
+    period: Option<String>,
+    at_line_start: bool,
+}
+
+fn open_append(path: &Path) -> std::io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+}
+
+fn compress(from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut input = std::fs::File::open(from)?;
+    let mut encoder =
+        flate2::write::GzEncoder::new(std::fs::File::create(to)?, flate2::Compression::default());
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    std::fs::remove_file(from)
+}
+
+impl LogFile {
+    /// Opens the file for the stream, or for both streams when `stream` is `None`.
+    pub fn open(
+        path: PathBuf,
+        stream: Option<Stream>,
+        rotation: Rotation,
+        prefix: Option<Prefix>,
+    ) -> std::io::Result<Self> {
+        let file = open_append(&path)?;
+        let metadata = file.metadata()?;
+        let modified = match metadata.len() {
+            0 => chrono::Local::now(),
+            _ => metadata.modified()?.into(),
+        };
+
+        Ok(Self {
+            period: rotation.period.map(|p| p.key(modified)),
+            size: metadata.len(),
+            path,
+            stream,
+            rotation,
+            prefixer: LinePrefixer::new(prefix),
+            context: LineContext {
+                attempt: 0,
+                pid: None,
+                stream: Stream::Stdout,
+            },
+            file,
+            at_line_start: true,
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        if self.rotation.compress {
+            path.push(".gz");
+        }
+        path.into()
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let keep = self.rotation.keep;
+
+        if keep == 0 {
+            self.file = std::fs::File::create(&self.path)?;
+            self.size = 0;
+            return Ok(());
+        }
+
+        match std::fs::remove_file(self.rotated(keep)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => (),
+        }
+
+        for n in (1..keep).rev() {
+            let from = self.rotated(n);
+            if from.exists() {
+                std::fs::rename(from, self.rotated(n + 1))?;
+            }
+        }
+
+        if self.rotation.compress {
+            let mut uncompressed = self.path.clone().into_os_string();
+            uncompressed.push(".1");
+            std::fs::rename(&self.path, &uncompressed)?;
+            compress(Path::new(&uncompressed), &self.rotated(1))?;
+        } else {
+            std::fs::rename(&self.path, self.rotated(1))?;
+        }
+
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+
+    fn should_rotate(&mut self, len: usize) -> bool {
+        if !self.at_line_start || self.size == 0 {
+            return false;
+        }
+
+        let oversized = self
+            .rotation
+            .max_size
+            .map_or(false, |max| self.size + len as u64 > max);
+
+        let period = self.rotation.period.map(|p| p.key(chrono::Local::now()));
+        let expired = period != self.period;
+        self.period = period;
+
+        oversized || expired
+    }
+}
+
+impl OutputSink for LogFile {
+    fn begin(&mut self, attempt: usize, pid: Option<u32>) -> std::io::Result<()> {
+        self.context.attempt = attempt;
+        self.context.pid = pid;
+        Ok(())
+    }
+
+    fn write(&mut self, stream: Stream, data: &[u8]) -> std::io::Result<()> {
+        use std::io::Write;
+
+        if self.stream.map_or(false, |s| s != stream) {
+            return Ok(());
+        }
+
+        let context = LineContext {
+            stream,
+            ..self.context
+        };
+        let data = self.prefixer.apply(&context, data);
+
+        if self.should_rotate(data.len()) {
+            self.rotate()?;
+        }
+
+        self.file.write_all(&data)?;
+        self.size += data.len() as u64;
+        self.at_line_start = data.ends_with(b"\n");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotation(max_size: u64, keep: usize, compress: bool) -> Rotation {
+        Rotation {
+            max_size: Some(max_size),
+            period: None,
+            keep,
+            compress,
+        }
+    }
+
+    #[test]
+    fn rotate_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        let mut log = LogFile::open(path.clone(), None, rotation(8, 2, false), None).unwrap();
+
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            log.write(Stream::Stdout, line.as_bytes()).unwrap();
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("out.log.1")).unwrap(),
+            "third\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("out.log.2")).unwrap(),
+            "second\n"
+        );
+        assert!(!dir.path().join("out.log.3").exists());
+    }
+
+    #[test]
+    fn rotate_at_line_boundary() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        let mut log = LogFile::open(path.clone(), None, rotation(4, 1, false), None).unwrap();
+
+        log.write(Stream::Stdout, b"abc").unwrap();
+        log.write(Stream::Stdout, b"def\n").unwrap();
+        log.write(Stream::Stdout, b"ghi\n").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "ghi\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("out.log.1")).unwrap(),
+            "abcdef\n"
+        );
+    }
+
+    #[test]
+    fn compress_rotated_file() {
+        use std::io::Read;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        let mut log = LogFile::open(path.clone(), None, rotation(4, 1, true), None).unwrap();
+
+        log.write(Stream::Stdout, b"abcd\n").unwrap();
+        log.write(Stream::Stdout, b"efgh\n").unwrap();
+
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(dir.path().join("out.log.1.gz")).unwrap())
+            .read_to_string(&mut decoded)
+            .unwrap();
+
+        assert_eq!(decoded, "abcd\n");
+        assert!(!dir.path().join("out.log.1").exists());
+    }
+
+    #[test]
+    fn write_only_the_stream() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("err.log");
+        let prefix = "{stream} ".parse::<Prefix>().ok();
+        let mut log = LogFile::open(
+            path.clone(),
+            Some(Stream::Stderr),
+            Rotation::default(),
+            prefix,
+        )
+        .unwrap();
+
+        log.write(Stream::Stdout, b"out\n").unwrap();
+        log.write(Stream::Stderr, b"err\n").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "stderr err\n");
+    }
+}
@@ -0,0 +1,658 @@
+use super::*;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl std::fmt::Display for Stream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stream::Stdout => write!(f, "stdout"),
+            Stream::Stderr => write!(f, "stderr"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Timestamp,
+    Relative,
+    Attempt,
+    Pid,
+    Stream,
+}
+
+/// Template prepended to every line of the child's output.
+///
+/// Supported placeholders are `{ts}` (RFC3339 timestamp), `{ts:rel}` (seconds since cx started),
+/// `{attempt}`, `{pid}` and `{stream}`. Literal braces are written as `{{` and `}}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prefix {
+    segments: Vec<Segment>,
+}
+
+impl std::str::FromStr for Prefix {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| format!("unclosed placeholder in '{}'", template))?;
+
+                    let segment = match &rest[..end] {
+                        "ts" => Segment::Timestamp,
+                        "ts:rel" => Segment::Relative,
+                        "attempt" => Segment::Attempt,
+                        "pid" => Segment::Pid,
+                        "stream" => Segment::Stream,
+                        other => return Err(format!("unknown placeholder '{{{}}}'", other)),
+                    };
+
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(segment);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(format!("unmatched '}}' in '{}'", template)),
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+impl Prefix {
+    /// Prefix of the text as it is, without placeholders.
+    pub fn literal(text: &str) -> Self {
+        Self {
+            segments: vec![Segment::Text(text.to_string())],
+        }
+    }
+}
+
+/// Information about the line being written, used to render a `Prefix`.
+#[derive(Debug, Clone, Copy)]
+pub struct LineContext {
+    pub attempt: usize,
+    pub pid: Option<u32>,
+    pub stream: Stream,
+}
+
+impl Prefix {
+    fn render(&self, context: &LineContext, origin: std::time::Instant) -> String {
+        use std::fmt::Write;
+
+        let mut rendered = String::new();
+
+        for segment in &self.segments {
+            let _ = match segment {
+                Segment::Text(text) => write!(rendered, "{}", text),
+                Segment::Timestamp => write!(
+                    rendered,
+                    "{}",
+                    chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false)
+                ),
+                Segment::Relative => write!(rendered, "{:.3}", origin.elapsed().as_secs_f64()),
+                Segment::Attempt => write!(rendered, "{}", context.attempt),
+                Segment::Pid => match context.pid {
+                    Some(pid) => write!(rendered, "{}", pid),
+                    None => write!(rendered, "-"),
+                },
+                Segment::Stream => write!(rendered, "{}", context.stream),
+            };
+        }
+
+        rendered
+    }
+}
+
+/// Inserts a `Prefix` at the start of every line, keeping track of partial lines per stream.
+///
+/// The data is handled as raw bytes, and a partial line is passed on as soon as it arrives so
+/// that progress output is not held back.
+pub struct LinePrefixer {
+    prefix: Option<Prefix>,
+    origin: std::time::Instant,
+    at_line_start: [bool; 2],
+}
+
+impl LinePrefixer {
+    pub fn new(prefix: Option<Prefix>) -> Self {
+        Self {
+            prefix,
+            origin: std::time::Instant::now(),
+            at_line_start: [true; 2],
+        }
+    }
+
+    pub fn apply<'d>(
+        &mut self,
+        context: &LineContext,
+        data: &'d [u8],
+    ) -> std::borrow::Cow<'d, [u8]> {
+        let prefix = match &self.prefix {
+            Some(prefix) => prefix,
+            None => return data.into(),
+        };
+
+        let at_line_start = &mut self.at_line_start[context.stream as usize];
+        let mut out = Vec::with_capacity(data.len());
+
+        for line in data.split_inclusive(|b| *b == b'\n') {
+            if *at_line_start {
+                out.extend_from_slice(prefix.render(context, self.origin).as_bytes());
+            }
+            out.extend_from_slice(line);
+            *at_line_start = line.ends_with(b"\n");
+        }
+
+        out.into()
+    }
+}
+
+/// Destination of the child's output.
+pub trait OutputSink: Send {
+    /// Called once the child of a new attempt has been spawned.
+    fn begin(&mut self, _attempt: usize, _pid: Option<u32>) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn write(&mut self, stream: Stream, data: &[u8]) -> std::io::Result<()>;
+
+    /// Called once the child of the attempt has exited.
+    fn finish(&mut self, _exit: &Exit) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once no more attempts will be made.
+    fn close(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: OutputSink + ?Sized> OutputSink for Box<S> {
+    fn begin(&mut self, attempt: usize, pid: Option<u32>) -> std::io::Result<()> {
+        (**self).begin(attempt, pid)
+    }
+
+    fn write(&mut self, stream: Stream, data: &[u8]) -> std::io::Result<()> {
+        (**self).write(stream, data)
+    }
+
+    fn finish(&mut self, exit: &Exit) -> std::io::Result<()> {
+        (**self).finish(exit)
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        (**self).close()
+    }
+}
+
+/// Passes the output through to stdout and stderr of cx.
+pub struct Terminal {
+    prefixer: LinePrefixer,
+    context: LineContext,
+}
+
+impl Terminal {
+    pub fn new(prefix: Option<Prefix>) -> Self {
+        Self {
+            prefixer: LinePrefixer::new(prefix),
+            context: LineContext {
+                attempt: 0,
+                pid: None,
+                stream: Stream::Stdout,
+            },
+        }
+    }
+}
+
+impl OutputSink for Terminal {
+    fn begin(&mut self, attempt: usize, pid: Option<u32>) -> std::io::Result<()> {
+        self.context.attempt = attempt;
+        self.context.pid = pid;
+        Ok(())
+    }
+
+    fn write(&mut self, stream: Stream, data: &[u8]) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let context = LineContext {
+            stream,
+            ..self.context
+        };
+        let data = self.prefixer.apply(&context, data);
+
+        match stream {
+            Stream::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(&data)?;
+                stdout.flush()
+            }
+            Stream::Stderr => {
+                let mut stderr = std::io::stderr().lock();
+                stderr.write_all(&data)?;
+                stderr.flush()
+            }
+        }
+    }
+}
+
+/// Holds back partial lines so that only whole lines reach the inner sink, keeping the lines of
+/// concurrent commands from being mixed up.
+pub struct LineBuffered<S> {
+    inner: S,
+    partial: [Vec<u8>; 2],
+}
+
+impl<S: OutputSink> LineBuffered<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            partial: [Vec::new(), Vec::new()],
+        }
+    }
+}
+
+impl<S: OutputSink> OutputSink for LineBuffered<S> {
+    fn begin(&mut self, attempt: usize, pid: Option<u32>) -> std::io::Result<()> {
+        self.inner.begin(attempt, pid)
+    }
+
+    fn write(&mut self, stream: Stream, data: &[u8]) -> std::io::Result<()> {
+        let partial = &mut self.partial[stream as usize];
+        partial.extend_from_slice(data);
+
+        let end = match partial.iter().rposition(|b| *b == b'\n') {
+            Some(end) => end + 1,
+            None => return Ok(()),
+        };
+
+        let lines = partial.drain(..end).collect::<Vec<_>>();
+        self.inner.write(stream, &lines)
+    }
+
+    fn finish(&mut self, exit: &Exit) -> std::io::Result<()> {
+        for stream in [Stream::Stdout, Stream::Stderr] {
+            let partial = std::mem::take(&mut self.partial[stream as usize]);
+            if !partial.is_empty() {
+                self.inner.write(stream, &partial)?;
+            }
+        }
+
+        self.inner.finish(exit)
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.inner.close()
+    }
+}
+
+/// What the writer thread is asked to do with the sinks.
+enum Command {
+    Begin(usize, Option<u32>),
+    Write(Stream, Vec<u8>),
+    Finish(Exit),
+    Close,
+    /// acknowledged once every command before it has been carried out
+    Sync(tokio::sync::oneshot::Sender<()>),
+}
+
+/// Told about the child of each attempt once it has been spawned, before any of its output.
+pub trait SpawnObserver: Send + Sync {
+    fn spawned(&self, attempt: usize, pid: Option<u32>);
+}
+
+/// Commands queued for the writer thread. Once it is full, reading the child waits for the sinks
+/// to catch up, so that a slow consumer throttles the child instead of filling the memory.
+const QUEUE: usize = 16;
+
+/// Fans the output out to every sink, within the `OutputLimit`. The sinks are written on a
+/// thread of their own, so that blocking writes, rotation and compression do not stall the
+/// runtime.
+pub struct Output {
+    writer: tokio::sync::mpsc::Sender<Command>,
+    limiter: std::sync::Mutex<Limiter>,
+    exceeded: tokio::sync::watch::Sender<bool>,
+    tail: Option<std::sync::Arc<TailBuffer>>,
+    observer: Option<std::sync::Arc<dyn SpawnObserver>>,
+}
+
+impl Output {
+    pub fn new(sinks: Vec<Box<dyn OutputSink>>) -> Self {
+        let (writer, commands) = tokio::sync::mpsc::channel(QUEUE);
+        std::thread::spawn(move || Self::write_sinks(sinks, commands));
+
+        Self {
+            writer,
+            limiter: std::sync::Mutex::new(Limiter::new(OutputLimit::default())),
+            exceeded: tokio::sync::watch::channel(false).0,
+            tail: None,
+            observer: None,
+        }
+    }
+
+    pub fn with_limit(self, limit: OutputLimit) -> Self {
+        Self {
+            limiter: std::sync::Mutex::new(Limiter::new(limit)),
+            ..self
+        }
+    }
+
+    /// Keeps the last lines of each attempt in `tail`, before any limit applies.
+    pub fn with_tail(self, tail: Option<std::sync::Arc<TailBuffer>>) -> Self {
+        Self { tail, ..self }
+    }
+
+    pub fn with_observer(self, observer: std::sync::Arc<dyn SpawnObserver>) -> Self {
+        Self {
+            observer: Some(observer),
+            ..self
+        }
+    }
+
+    /// Carries out the commands until the `Output` is dropped. A sink whose reader has gone away,
+    /// as with `| head`, is dropped instead of failing on every write.
+    fn write_sinks(
+        mut sinks: Vec<Box<dyn OutputSink>>,
+        mut commands: tokio::sync::mpsc::Receiver<Command>,
+    ) {
+        use std::io::Write;
+
+        let mut each = |f: &mut dyn FnMut(&mut dyn OutputSink) -> std::io::Result<()>| {
+            sinks.retain_mut(|sink| match f(sink.as_mut()) {
+                Ok(()) => true,
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => false,
+                Err(e) => {
+                    let _ = writeln!(std::io::stderr(), "cx: failed to write output: {}", e);
+                    true
+                }
+            });
+        };
+
+        while let Some(command) = commands.blocking_recv() {
+            match command {
+                Command::Begin(attempt, pid) => each(&mut |sink| sink.begin(attempt, pid)),
+                Command::Write(stream, data) => each(&mut |sink| sink.write(stream, &data)),
+                Command::Finish(exit) => each(&mut |sink| sink.finish(&exit)),
+                Command::Close => each(&mut |sink| sink.close()),
+                Command::Sync(done) => {
+                    let _ = done.send(());
+                }
+            }
+        }
+    }
+
+    fn limiter(&self) -> std::sync::MutexGuard<'_, Limiter> {
+        self.limiter.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    async fn send(&self, command: Command) {
+        let _ = self.writer.send(command).await;
+    }
+
+    /// Waits until the sinks have caught up with everything sent so far.
+    async fn sync(&self) {
+        let (done, synced) = tokio::sync::oneshot::channel();
+        self.send(Command::Sync(done)).await;
+        let _ = synced.await;
+    }
+
+    pub(super) async fn begin(&self, attempt: usize, pid: Option<u32>) {
+        if let Some(observer) = &self.observer {
+            observer.spawned(attempt, pid);
+        }
+        self.limiter().begin();
+        self.exceeded.send_replace(false);
+        if let Some(tail) = &self.tail {
+            tail.begin(attempt);
+        }
+        self.send(Command::Begin(attempt, pid)).await;
+    }
+
+    pub(super) async fn write(&self, stream: Stream, data: &[u8]) {
+        if let Some(tail) = &self.tail {
+            tail.push(stream, data);
+        }
+
+        let admitted = self.limiter().admit(stream, data);
+
+        if admitted.kill {
+            self.exceeded.send_replace(true);
+        }
+        if !admitted.data.is_empty() {
+            self.send(Command::Write(stream, admitted.data.to_vec()))
+                .await;
+        }
+        if let Some(marker) = admitted.marker {
+            self.send(Command::Write(stream, marker.into_owned())).await;
+        }
+    }
+
+    /// Resolves once the sinks have finished the attempt.
+    pub(super) async fn finish(&self, exit: &Exit) {
+        let held = self.limiter().finish();
+
+        for (stream, data) in held {
+            self.send(Command::Write(stream, data.into_owned())).await;
+        }
+        self.send(Command::Finish(exit.clone())).await;
+        self.sync().await;
+    }
+
+    /// Resolves once the output of the attempt exceeds the limit under `LimitPolicy::Kill`.
+    pub(super) async fn limit_exceeded(&self) {
+        let mut exceeded = self.exceeded.subscribe();
+        let _ = exceeded.wait_for(|exceeded| *exceeded).await;
+    }
+
+    /// Resolves once the sinks have been closed.
+    pub async fn close(&self) {
+        self.send(Command::Close).await;
+        self.sync().await;
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self::new(vec![Box::new(Terminal::new(None))])
+    }
+}
+
+/// Reads `reader` until EOF and passes the data to the sinks as the given stream.
+pub(super) async fn forward<R>(
+    mut reader: R,
+    stream: Stream,
+    output: &Output,
+) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut buf = vec![0u8; 8192];
+
+    loop {
+        let len = reader.read(&mut buf).await?;
+
+        if len == 0 {
+            break;
+        }
+
+        output.write(stream, &buf[..len]).await;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> LineContext {
+        LineContext {
+            attempt: 3,
+            pid: Some(42),
+            stream: Stream::Stderr,
+        }
+    }
+
+    fn render(prefix: &Prefix) -> String {
+        prefix.render(&context(), std::time::Instant::now())
+    }
+
+    type Captured = std::sync::Arc<std::sync::Mutex<Vec<(Stream, Vec<u8>)>>>;
+
+    struct Capture(Captured);
+
+    impl OutputSink for Capture {
+        fn write(&mut self, stream: Stream, data: &[u8]) -> std::io::Result<()> {
+            self.0.lock().unwrap().push((stream, data.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn parse_and_render_prefix() {
+        let prefix = "[#{attempt} {pid} {stream}] ".parse::<Prefix>().unwrap();
+        assert_eq!(render(&prefix), "[#3 42 stderr] ");
+    }
+
+    #[test]
+    fn parse_escaped_braces() {
+        let prefix = "{{{attempt}}}".parse::<Prefix>().unwrap();
+        assert_eq!(render(&prefix), "{3}");
+    }
+
+    #[test]
+    fn parse_invalid_prefix() {
+        assert!("{unknown}".parse::<Prefix>().is_err());
+        assert!("{attempt".parse::<Prefix>().is_err());
+        assert!("attempt}".parse::<Prefix>().is_err());
+    }
+
+    #[test]
+    fn render_timestamp() {
+        let prefix = "{ts}".parse::<Prefix>().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(&render(&prefix)).is_ok());
+    }
+
+    #[test]
+    fn prefix_lines() {
+        let prefix = "#{attempt} ".parse::<Prefix>().ok();
+        let mut prefixer = LinePrefixer::new(prefix);
+        let actual = prefixer.apply(&context(), b"abc\ndef\n\xffgh");
+        assert_eq!(&actual[..], b"#3 abc\n#3 def\n#3 \xffgh");
+    }
+
+    #[test]
+    fn prefix_partial_lines_per_stream() {
+        let prefix = "{stream} ".parse::<Prefix>().ok();
+        let mut prefixer = LinePrefixer::new(prefix);
+        let stdout = LineContext {
+            stream: Stream::Stdout,
+            ..context()
+        };
+
+        let mut actual = Vec::new();
+        actual.extend_from_slice(&prefixer.apply(&stdout, b"ab"));
+        actual.extend_from_slice(&prefixer.apply(&context(), b"x\n"));
+        actual.extend_from_slice(&prefixer.apply(&stdout, b"c\nd"));
+
+        assert_eq!(actual, b"stdout abstderr x\nc\nstdout d");
+    }
+
+    #[test]
+    fn pass_through_without_prefix() {
+        let mut prefixer = LinePrefixer::new(None);
+        let actual = prefixer.apply(&context(), b"abc\ndef");
+        assert_eq!(&actual[..], b"abc\ndef");
+    }
+
+    #[test]
+    fn pass_whole_lines() {
+        let captured = Captured::default();
+        let mut sink = LineBuffered::new(Capture(captured.clone()));
+
+        sink.write(Stream::Stdout, b"ab").unwrap();
+        sink.write(Stream::Stdout, b"c\nde\nf").unwrap();
+        sink.write(Stream::Stderr, b"x").unwrap();
+        sink.finish(&Exit::Code(0)).unwrap();
+
+        let expected = vec![
+            (Stream::Stdout, b"abc\nde\n".to_vec()),
+            (Stream::Stdout, b"f".to_vec()),
+            (Stream::Stderr, b"x".to_vec()),
+        ];
+        assert_eq!(*captured.lock().unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn forward_to_every_sink() {
+        let first = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let second = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let output = Output::new(vec![
+            Box::new(Capture(first.clone())),
+            Box::new(Capture(second.clone())),
+        ]);
+
+        forward(&b"abc\n"[..], Stream::Stderr, &output)
+            .await
+            .unwrap();
+        output.close().await;
+
+        let expected = vec![(Stream::Stderr, b"abc\n".to_vec())];
+        assert_eq!(*first.lock().unwrap(), expected);
+        assert_eq!(*second.lock().unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn drop_sinks_whose_reader_is_gone() {
+        struct Closed(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+        impl OutputSink for Closed {
+            fn write(&mut self, _: Stream, _: &[u8]) -> std::io::Result<()> {
+                self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+        }
+
+        let writes = std::sync::Arc::default();
+        let captured = Captured::default();
+        let output = Output::new(vec![
+            Box::new(Closed(std::sync::Arc::clone(&writes))),
+            Box::new(Capture(captured.clone())),
+        ]);
+
+        output.write(Stream::Stdout, b"a").await;
+        output.write(Stream::Stdout, b"b").await;
+        output.close().await;
+
+        assert_eq!(writes.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(captured.lock().unwrap().len(), 2);
+    }
+}
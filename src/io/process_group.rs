@@ -0,0 +1,78 @@
+use std::sync::Mutex;
+
+/// Process groups of the children running in one. Being in the background, they miss the signals
+/// the terminal sends to cx, so those are forwarded to every group before cx exits.
+static GROUPS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+static FORWARD: std::sync::Once = std::sync::Once::new();
+
+fn groups() -> std::sync::MutexGuard<'static, Vec<u32>> {
+    GROUPS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Process group led by a child, killed when dropped before the child has exited if
+/// `kill_on_drop` is set.
+pub(super) struct ProcessGroup {
+    pgid: u32,
+    kill_on_drop: bool,
+}
+
+impl ProcessGroup {
+    /// Must be called within a tokio runtime, which the signals are forwarded from.
+    pub(super) fn register(pgid: u32, kill_on_drop: bool) -> Self {
+        FORWARD.call_once(forward_termination);
+        groups().push(pgid);
+
+        Self { pgid, kill_on_drop }
+    }
+
+    /// Sends the signal to every process in the group. A group that is already gone is not an
+    /// error.
+    pub(super) fn signal(&self, signal: i32) -> std::io::Result<()> {
+        match unsafe { libc::kill(-(self.pgid as libc::pid_t), signal) } {
+            0 => Ok(()),
+            _ => match std::io::Error::last_os_error() {
+                e if e.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+                e => Err(e),
+            },
+        }
+    }
+
+    /// Leaves the group alone on drop, once the child has exited.
+    pub(super) fn release(mut self) {
+        self.kill_on_drop = false;
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if self.kill_on_drop {
+            let _ = self.signal(libc::SIGKILL);
+        }
+
+        groups().retain(|pgid| *pgid != self.pgid);
+    }
+}
+
+/// Passes SIGINT, SIGTERM and SIGHUP on to the process groups still running, then exits with
+/// the status of a process killed by the signal.
+fn forward_termination() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    for number in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        let mut received = match signal(SignalKind::from_raw(number)) {
+            Ok(received) => received,
+            Err(_) => continue,
+        };
+
+        tokio::spawn(async move {
+            received.recv().await;
+
+            for pgid in groups().iter() {
+                unsafe { libc::kill(-(*pgid as libc::pid_t), number) };
+            }
+
+            std::process::exit(128 + number);
+        });
+    }
+}
@@ -0,0 +1,264 @@
+use super::*;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Runs the command on a pseudo-terminal so that it behaves as if attached to a TTY.
+#[derive(new, Default)]
+pub struct PtyCmdExecutor {
+    options: SpawnOptions,
+    output: std::sync::Arc<Output>,
+}
+
+struct Pty {
+    master: std::fs::File,
+    slave: std::fs::File,
+}
+
+impl Pty {
+    fn open() -> std::io::Result<Self> {
+        let mut master: RawFd = -1;
+        let mut slave: RawFd = -1;
+        let mut size = window_size(libc::STDIN_FILENO);
+
+        let result = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                size.as_mut().map_or(std::ptr::null_mut(), |s| s as *mut _),
+            )
+        };
+
+        if result != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let (master, slave) = unsafe {
+            (
+                std::fs::File::from_raw_fd(master),
+                std::fs::File::from_raw_fd(slave),
+            )
+        };
+
+        set_cloexec(master.as_raw_fd())?;
+        set_cloexec(slave.as_raw_fd())?;
+
+        Ok(Self { master, slave })
+    }
+}
+
+fn set_cloexec(fd: RawFd) -> std::io::Result<()> {
+    match unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } {
+        -1 => Err(std::io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+fn window_size(fd: RawFd) -> Option<libc::winsize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    match unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } {
+        0 => Some(size),
+        _ => None,
+    }
+}
+
+fn forward_window_size(from: RawFd, to: RawFd) {
+    if let Some(size) = window_size(from) {
+        unsafe { libc::ioctl(to, libc::TIOCSWINSZ, &size) };
+    }
+}
+
+/// Puts the terminal into raw mode and restores the original settings on drop.
+struct RawMode {
+    fd: RawFd,
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enter(fd: RawFd) -> Option<Self> {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+
+        if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+            return None;
+        }
+
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+
+        match unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } {
+            0 => Some(Self { fd, original }),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Copies stdin to the pty master until stdin is exhausted or `stop` is set. Reads fd 0 directly,
+/// as bytes left in the buffer of `std::io::stdin()` would not wake up `poll` again.
+fn forward_stdin(mut master: std::fs::File, stop: Arc<AtomicBool>) {
+    use std::io::Write;
+
+    let mut buf = [0u8; 4096];
+
+    while !stop.load(Ordering::Relaxed) {
+        let mut fds = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        match unsafe { libc::poll(&mut fds, 1, 100) } {
+            0 => continue,
+            n if n < 0 => break,
+            _ => (),
+        }
+
+        let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+        match n {
+            0 => break,
+            n if n < 0 => match std::io::Error::last_os_error().kind() {
+                std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock => continue,
+                _ => break,
+            },
+            n => {
+                if master.write_all(&buf[..n as usize]).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl PipedCmdExecute for PtyCmdExecutor {
+    async fn piped_exec(&self, command: &str, attempt: usize) -> std::io::Result<Exit> {
+        use std::os::unix::process::CommandExt;
+
+        let (program, options) = PipedCmdExecutor::parse_command(command);
+
+        let pty = Pty::open()?;
+
+        let mut command = std::process::Command::new(program);
+        command
+            .args(options)
+            .stdin(pty.slave.try_clone()?)
+            .stdout(pty.slave.try_clone()?)
+            .stderr(pty.slave.try_clone()?);
+
+        // Leading a session of its own, the child leads a process group as well, which setpgid
+        // would fail for.
+        let options = SpawnOptions {
+            process_group: false,
+            ..self.options.clone()
+        };
+
+        unsafe {
+            command.pre_exec(move || {
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                if libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                options.apply()
+            });
+        }
+
+        let mut child = tokio::process::Command::from(command).spawn()?;
+
+        drop(pty.slave);
+
+        let master_fd = pty.master.as_raw_fd();
+        let raw_mode = RawMode::enter(libc::STDIN_FILENO);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let input = {
+            let master = pty.master.try_clone()?;
+            let stop = stop.clone();
+            std::thread::spawn(move || forward_stdin(master, stop))
+        };
+
+        // The output is merged into a single stream by the terminal. Reading the master fails
+        // with EIO once every slave descriptor has been closed, which marks the end of output.
+        self.output.begin(attempt, child.id()).await;
+
+        let output = forward(
+            tokio::fs::File::from_std(pty.master.try_clone()?),
+            Stream::Stdout,
+            &self.output,
+        );
+
+        let mut window_change =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::window_change())?;
+
+        let wait = async {
+            let mut killed = false;
+
+            loop {
+                tokio::select! {
+                    status = child.wait() => break status,
+                    _ = window_change.recv() => forward_window_size(libc::STDIN_FILENO, master_fd),
+                    _ = self.output.limit_exceeded(), if !killed => {
+                        // The child leads the session, so this reaches what it has started too.
+                        if let Some(pid) = child.id() {
+                            unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) };
+                        }
+                        killed = true;
+                    }
+                }
+            }
+        };
+
+        let (exit_status, _) = tokio::join!(wait, output);
+
+        stop.store(true, Ordering::Relaxed);
+        let _ = input.join();
+        drop(raw_mode);
+
+        let exit = exit_from_status(exit_status?)?;
+        self.output.finish(&exit).await;
+
+        Ok(exit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn should_success_given_suitable_command() {
+        let actual = PtyCmdExecutor::default()
+            .piped_exec("echo abcd", 1)
+            .await
+            .unwrap();
+        let expected = Exit::Code(0);
+        assert_eq!(actual, expected);
+    }
+
+    #[tokio::test]
+    async fn should_run_command_on_terminal() {
+        let actual = PtyCmdExecutor::default()
+            .piped_exec("test -t 1", 1)
+            .await
+            .unwrap();
+        let expected = Exit::Code(0);
+        assert_eq!(actual, expected);
+    }
+
+    #[tokio::test]
+    async fn should_failure_when_command_not_found() {
+        let actual = PtyCmdExecutor::default()
+            .piped_exec("failed", 1)
+            .await
+            .is_err();
+        assert!(actual);
+    }
+}
@@ -0,0 +1,227 @@
+use super::*;
+use std::hash::{Hash, Hasher};
+
+fn line_hash(stream: Stream, line: &[u8]) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    (stream as u8).hash(&mut hasher);
+    line.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Suppresses the output of an attempt when it is identical to the output of the previous one.
+///
+/// Lines matching the previous attempt are held back until they differ, at which point they are
+/// passed on and the rest of the attempt streams normally.
+pub struct Deduplicated<S> {
+    inner: S,
+    attempt: usize,
+    previous: Option<(usize, Vec<u64>)>,
+    current: Vec<u64>,
+    held: Vec<(Stream, Vec<u8>)>,
+    partial: [Vec<u8>; 2],
+    diverged: bool,
+    at_line_start: bool,
+}
+
+impl<S: OutputSink> Deduplicated<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            attempt: 0,
+            previous: None,
+            current: Vec::new(),
+            held: Vec::new(),
+            partial: [Vec::new(), Vec::new()],
+            diverged: true,
+            at_line_start: true,
+        }
+    }
+
+    fn pass(&mut self, stream: Stream, data: &[u8]) -> std::io::Result<()> {
+        if let Some(last) = data.last() {
+            self.at_line_start = *last == b'\n';
+        }
+        self.inner.write(stream, data)
+    }
+
+    fn matches_previous(&self, hash: u64) -> bool {
+        match &self.previous {
+            Some((_, hashes)) => hashes.get(self.current.len()) == Some(&hash),
+            None => false,
+        }
+    }
+
+    /// Passes on everything held back so far, including partial lines.
+    fn diverge(&mut self) -> std::io::Result<()> {
+        self.diverged = true;
+
+        for (stream, line) in std::mem::take(&mut self.held) {
+            self.pass(stream, &line)?;
+        }
+
+        for stream in [Stream::Stdout, Stream::Stderr] {
+            let partial = std::mem::take(&mut self.partial[stream as usize]);
+            self.pass(stream, &partial)?;
+            self.partial[stream as usize] = partial;
+        }
+
+        Ok(())
+    }
+
+    /// Compares a complete line, passing it on unless it is held back.
+    fn line(&mut self, stream: Stream, line: Vec<u8>, passed: bool) -> std::io::Result<()> {
+        let hash = line_hash(stream, &line);
+
+        if !self.diverged && self.matches_previous(hash) {
+            self.held.push((stream, line));
+        } else if !self.diverged {
+            self.diverge()?;
+            self.pass(stream, &line)?;
+        } else if !passed {
+            self.pass(stream, &line)?;
+        }
+
+        self.current.push(hash);
+        Ok(())
+    }
+}
+
+impl<S: OutputSink> OutputSink for Deduplicated<S> {
+    fn begin(&mut self, attempt: usize, pid: Option<u32>) -> std::io::Result<()> {
+        self.attempt = attempt;
+        self.current.clear();
+        self.held.clear();
+        self.partial = [Vec::new(), Vec::new()];
+        self.diverged = self.previous.is_none();
+        self.inner.begin(attempt, pid)
+    }
+
+    fn write(&mut self, stream: Stream, data: &[u8]) -> std::io::Result<()> {
+        for chunk in data.split_inclusive(|b| *b == b'\n') {
+            let passed = self.diverged;
+            if passed {
+                self.pass(stream, chunk)?;
+            }
+
+            self.partial[stream as usize].extend_from_slice(chunk);
+
+            if chunk.ends_with(b"\n") {
+                let line = std::mem::take(&mut self.partial[stream as usize]);
+                self.line(stream, line, passed)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, exit: &Exit) -> std::io::Result<()> {
+        for stream in [Stream::Stdout, Stream::Stderr] {
+            let line = std::mem::take(&mut self.partial[stream as usize]);
+            if !line.is_empty() {
+                let passed = self.diverged;
+                self.line(stream, line, passed)?;
+            }
+        }
+
+        let identical = match &self.previous {
+            Some((_, hashes)) => !self.diverged && hashes.len() == self.current.len(),
+            None => false,
+        };
+
+        match (identical, &self.previous) {
+            (true, Some((previous, _))) if !self.held.is_empty() => {
+                let message = format!(
+                    "{}cx: attempt {} output identical to attempt {} ({} lines suppressed)\n",
+                    if self.at_line_start { "" } else { "\n" },
+                    self.attempt,
+                    previous,
+                    self.held.len()
+                );
+                self.held.clear();
+                self.pass(Stream::Stderr, message.as_bytes())?;
+            }
+            _ => self.diverge()?,
+        }
+
+        self.previous = Some((self.attempt, std::mem::take(&mut self.current)));
+        self.inner.finish(exit)
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.inner.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Collected = std::sync::Arc<std::sync::Mutex<Vec<u8>>>;
+
+    struct Collect(Collected);
+
+    impl OutputSink for Collect {
+        fn write(&mut self, _: Stream, data: &[u8]) -> std::io::Result<()> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    fn run(attempts: &[&[&str]]) -> String {
+        let collected = Collected::default();
+        let mut sink = Deduplicated::new(Collect(collected.clone()));
+
+        for (i, writes) in attempts.iter().enumerate() {
+            sink.begin(i + 1, None).unwrap();
+            for data in writes.iter() {
+                sink.write(Stream::Stdout, data.as_bytes()).unwrap();
+            }
+            sink.finish(&Exit::Code(1)).unwrap();
+        }
+
+        let collected = collected.lock().unwrap();
+        String::from_utf8(collected.clone()).unwrap()
+    }
+
+    #[test]
+    fn suppress_identical_output() {
+        let actual = run(&[&["a\nb\n"], &["a\n", "b\n"], &["a\nb\n"]]);
+        assert_eq!(
+            actual,
+            "a\nb\n\
+             cx: attempt 2 output identical to attempt 1 (2 lines suppressed)\n\
+             cx: attempt 3 output identical to attempt 2 (2 lines suppressed)\n"
+        );
+    }
+
+    #[test]
+    fn stream_differing_output() {
+        let actual = run(&[&["a\nb\n"], &["a\nc\nd"], &["a\n"]]);
+        assert_eq!(actual, "a\nb\na\nc\nda\n");
+    }
+
+    #[test]
+    fn pass_held_partial_line_on_divergence() {
+        let collected = Collected::default();
+        let mut sink = Deduplicated::new(Collect(collected.clone()));
+
+        for (attempt, line) in [(1, "b\n"), (2, "c\n")] {
+            sink.begin(attempt, None).unwrap();
+            sink.write(Stream::Stdout, b"a").unwrap();
+            sink.write(Stream::Stderr, line.as_bytes()).unwrap();
+            sink.write(Stream::Stdout, b"a\n").unwrap();
+            sink.finish(&Exit::Code(1)).unwrap();
+        }
+
+        assert_eq!(*collected.lock().unwrap(), b"ab\na\nac\na\n");
+    }
+
+    #[test]
+    fn compare_partial_last_line() {
+        let actual = run(&[&["a\nb"], &["a\n", "b"]]);
+        assert_eq!(
+            actual,
+            "a\nb\ncx: attempt 2 output identical to attempt 1 (2 lines suppressed)\n"
+        );
+    }
+}
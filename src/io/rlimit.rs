@@ -0,0 +1,56 @@
+/// Resource limits applied to the child process before exec.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourceLimits {
+    /// maximum size of the virtual address space (bytes)
+    pub address_space: Option<u64>,
+    /// maximum CPU time (sec)
+    pub cpu_time: Option<u64>,
+    /// maximum number of open file descriptors
+    pub open_files: Option<u64>,
+    /// maximum number of processes for the real user ID
+    pub processes: Option<u64>,
+    /// maximum size of a core dump (bytes)
+    pub core_size: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Applies the limits to the calling process. Intended to be called between fork and exec.
+    #[cfg(unix)]
+    pub(super) fn apply(&self) -> std::io::Result<()> {
+        fn set(resource: libc::c_int, soft: u64, hard: u64) -> std::io::Result<()> {
+            let limit = libc::rlimit {
+                rlim_cur: soft as libc::rlim_t,
+                rlim_max: hard as libc::rlim_t,
+            };
+
+            match unsafe { libc::setrlimit(resource as _, &limit) } {
+                0 => Ok(()),
+                _ => Err(std::io::Error::last_os_error()),
+            }
+        }
+
+        if let Some(bytes) = self.address_space {
+            set(libc::RLIMIT_AS as _, bytes, bytes)?;
+        }
+
+        // The hard limit is one second above the soft limit so that the child receives
+        // SIGXCPU rather than an immediate SIGKILL.
+        if let Some(sec) = self.cpu_time {
+            set(libc::RLIMIT_CPU as _, sec, sec.saturating_add(1))?;
+        }
+
+        if let Some(count) = self.open_files {
+            set(libc::RLIMIT_NOFILE as _, count, count)?;
+        }
+
+        if let Some(count) = self.processes {
+            set(libc::RLIMIT_NPROC as _, count, count)?;
+        }
+
+        if let Some(bytes) = self.core_size {
+            set(libc::RLIMIT_CORE as _, bytes, bytes)?;
+        }
+
+        Ok(())
+    }
+}
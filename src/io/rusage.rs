@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+/// CPU time consumed, as reported by `getrusage`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CpuTime {
+    pub user: Duration,
+    pub system: Duration,
+}
+
+impl CpuTime {
+    /// CPU time of all the children waited for so far. Always zero where unsupported.
+    pub fn children() -> Self {
+        #[cfg(unix)]
+        {
+            let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+
+            if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) } == 0 {
+                return Self {
+                    user: duration(usage.ru_utime),
+                    system: duration(usage.ru_stime),
+                };
+            }
+        }
+
+        Self::default()
+    }
+
+    /// CPU time consumed between the `earlier` reading and this one.
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            user: self.user.saturating_sub(earlier.user),
+            system: self.system.saturating_sub(earlier.system),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn duration(time: libc::timeval) -> Duration {
+    Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure_cpu_time_of_children() {
+        let before = CpuTime::children();
+        std::process::Command::new("sh")
+            .arg("-c")
+            .arg("i=0; while [ $i -lt 200000 ]; do i=$((i+1)); done")
+            .status()
+            .unwrap();
+        let cpu = CpuTime::children().since(&before);
+
+        assert!(cpu.user + cpu.system > Duration::ZERO);
+        assert_eq!(before.since(&CpuTime::children()), CpuTime::default());
+    }
+}
@@ -0,0 +1,104 @@
+#[cfg(unix)]
+const NAMES: &[(libc::c_int, &str)] = &[
+    (libc::SIGHUP, "SIGHUP"),
+    (libc::SIGINT, "SIGINT"),
+    (libc::SIGQUIT, "SIGQUIT"),
+    (libc::SIGILL, "SIGILL"),
+    (libc::SIGTRAP, "SIGTRAP"),
+    (libc::SIGABRT, "SIGABRT"),
+    (libc::SIGBUS, "SIGBUS"),
+    (libc::SIGFPE, "SIGFPE"),
+    (libc::SIGKILL, "SIGKILL"),
+    (libc::SIGUSR1, "SIGUSR1"),
+    (libc::SIGSEGV, "SIGSEGV"),
+    (libc::SIGUSR2, "SIGUSR2"),
+    (libc::SIGPIPE, "SIGPIPE"),
+    (libc::SIGALRM, "SIGALRM"),
+    (libc::SIGTERM, "SIGTERM"),
+    (libc::SIGCHLD, "SIGCHLD"),
+    (libc::SIGCONT, "SIGCONT"),
+    (libc::SIGSTOP, "SIGSTOP"),
+    (libc::SIGTSTP, "SIGTSTP"),
+    (libc::SIGTTIN, "SIGTTIN"),
+    (libc::SIGTTOU, "SIGTTOU"),
+    (libc::SIGURG, "SIGURG"),
+    (libc::SIGXCPU, "SIGXCPU"),
+    (libc::SIGXFSZ, "SIGXFSZ"),
+    (libc::SIGVTALRM, "SIGVTALRM"),
+    (libc::SIGPROF, "SIGPROF"),
+    (libc::SIGWINCH, "SIGWINCH"),
+    (libc::SIGIO, "SIGIO"),
+    (libc::SIGSYS, "SIGSYS"),
+];
+
+#[cfg(not(unix))]
+const NAMES: &[(i32, &str)] = &[];
+
+/// Returns the conventional name of the signal, e.g. `SIGSEGV`.
+pub fn name(signal: i32) -> String {
+    NAMES
+        .iter()
+        .find(|(number, _)| *number == signal)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| format!("signal {}", signal))
+}
+
+/// Signal given by name, with or without the `SIG` prefix, or by number.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Signal(pub i32);
+
+impl std::str::FromStr for Signal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(number) = s.parse::<i32>() {
+            return Ok(Signal(number));
+        }
+
+        let upper = s.to_ascii_uppercase();
+        let name = match upper.starts_with("SIG") {
+            true => upper,
+            false => format!("SIG{}", upper),
+        };
+
+        NAMES
+            .iter()
+            .find(|(_, known)| *known == name)
+            .map(|(number, _)| Signal(*number))
+            .ok_or_else(|| format!("unknown signal '{}'", s))
+    }
+}
+
+pub fn is_limit_exceeded(signal: i32) -> bool {
+    #[cfg(unix)]
+    return signal == libc::SIGXCPU || signal == libc::SIGXFSZ;
+
+    #[cfg(not(unix))]
+    return {
+        let _ = signal;
+        false
+    };
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_of_known_signal() {
+        assert_eq!(name(libc::SIGSEGV), "SIGSEGV");
+    }
+
+    #[test]
+    fn parse_signal() {
+        assert_eq!("TERM".parse(), Ok(Signal(libc::SIGTERM)));
+        assert_eq!("sigint".parse(), Ok(Signal(libc::SIGINT)));
+        assert_eq!("9".parse(), Ok(Signal(9)));
+        assert!("NOPE".parse::<Signal>().is_err());
+    }
+
+    #[test]
+    fn name_of_unknown_signal() {
+        assert_eq!(name(1000), "signal 1000");
+    }
+}
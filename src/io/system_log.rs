@@ -0,0 +1,233 @@
+#[cfg(unix)]
+use super::*;
+
+/// System logging service the output is sent to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogTarget {
+    /// RFC5424 messages over the syslog socket
+    Syslog,
+    /// native journal protocol
+    Journald,
+}
+
+impl std::str::FromStr for LogTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "syslog" => Ok(LogTarget::Syslog),
+            "journald" => Ok(LogTarget::Journald),
+            _ => Err(format!("invalid log target '{}'", s)),
+        }
+    }
+}
+
+impl LogTarget {
+    pub fn default_socket(&self) -> &'static str {
+        match self {
+            LogTarget::Syslog => "/dev/log",
+            LogTarget::Journald => "/run/systemd/journal/socket",
+        }
+    }
+}
+
+/// Partial lines longer than this are sent without waiting for the rest of the line.
+#[cfg(unix)]
+const LINE_LIMIT: usize = 8192;
+
+/// Sends every line of the output as a separate message to syslog or the journal, with stdout
+/// logged as info and stderr as warning.
+#[cfg(unix)]
+pub struct SystemLog {
+    target: LogTarget,
+    socket: std::os::unix::net::UnixDatagram,
+    identifier: String,
+    hostname: String,
+    attempt: usize,
+    pid: Option<u32>,
+    pending: [Vec<u8>; 2],
+}
+
+#[cfg(unix)]
+impl SystemLog {
+    pub fn connect(
+        target: LogTarget,
+        socket: &std::path::Path,
+        identifier: String,
+    ) -> std::io::Result<Self> {
+        let datagram = std::os::unix::net::UnixDatagram::unbound()?;
+        datagram.connect(socket)?;
+
+        Ok(Self {
+            target,
+            socket: datagram,
+            identifier,
+            hostname: hostname(),
+            attempt: 0,
+            pid: None,
+            pending: [Vec::new(), Vec::new()],
+        })
+    }
+
+    fn severity(stream: Stream) -> u8 {
+        match stream {
+            Stream::Stdout => 6,
+            Stream::Stderr => 4,
+        }
+    }
+
+    fn syslog_message(&self, stream: Stream, line: &[u8]) -> Vec<u8> {
+        // facility user
+        let priority = 8 + Self::severity(stream);
+        let pid = self.pid.map_or("-".to_string(), |pid| pid.to_string());
+
+        let mut message = format!(
+            "<{}>1 {} {} {} {} - [cx@32473 attempt=\"{}\"] ",
+            priority,
+            chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, false),
+            self.hostname,
+            self.identifier,
+            pid,
+            self.attempt
+        )
+        .into_bytes();
+        message.extend_from_slice(line);
+        message
+    }
+
+    fn journal_message(&self, stream: Stream, line: &[u8]) -> Vec<u8> {
+        let mut message = format!(
+            "PRIORITY={}\nSYSLOG_IDENTIFIER={}\nCX_ATTEMPT={}\n",
+            Self::severity(stream),
+            self.identifier,
+            self.attempt
+        )
+        .into_bytes();
+
+        if let Some(pid) = self.pid {
+            message.extend_from_slice(format!("SYSLOG_PID={}\n", pid).as_bytes());
+        }
+
+        message.extend_from_slice(b"MESSAGE=");
+        message.extend_from_slice(line);
+        message.push(b'\n');
+        message
+    }
+
+    fn send(&self, stream: Stream, line: &[u8]) -> std::io::Result<()> {
+        let message = match self.target {
+            LogTarget::Syslog => self.syslog_message(stream, line),
+            LogTarget::Journald => self.journal_message(stream, line),
+        };
+
+        self.socket.send(&message).map(|_| ())
+    }
+
+    fn flush(&mut self, stream: Stream) -> std::io::Result<()> {
+        let line = std::mem::take(&mut self.pending[stream as usize]);
+
+        match line.is_empty() {
+            true => Ok(()),
+            false => self.send(stream, &line),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl OutputSink for SystemLog {
+    fn begin(&mut self, attempt: usize, pid: Option<u32>) -> std::io::Result<()> {
+        self.attempt = attempt;
+        self.pid = pid;
+        Ok(())
+    }
+
+    fn write(&mut self, stream: Stream, data: &[u8]) -> std::io::Result<()> {
+        for chunk in data.split_inclusive(|b| *b == b'\n') {
+            self.pending[stream as usize].extend_from_slice(chunk);
+
+            let pending = &mut self.pending[stream as usize];
+            if pending.ends_with(b"\n") {
+                pending.pop();
+                self.flush(stream)?;
+            } else if pending.len() >= LINE_LIMIT {
+                self.flush(stream)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, _: &Exit) -> std::io::Result<()> {
+        self.flush(Stream::Stdout)?;
+        self.flush(Stream::Stderr)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    /// Socket standing in for the log daemon, removed along with the directory.
+    fn stand_in() -> (tempfile::TempDir, std::path::PathBuf, UnixDatagram) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log.sock");
+        let socket = UnixDatagram::bind(&path).unwrap();
+        socket.set_nonblocking(true).unwrap();
+        (dir, path, socket)
+    }
+
+    fn received(socket: &UnixDatagram) -> Vec<String> {
+        let mut messages = Vec::new();
+        let mut buf = [0u8; 65536];
+
+        while let Ok(len) = socket.recv(&mut buf) {
+            messages.push(String::from_utf8_lossy(&buf[..len]).into_owned());
+        }
+
+        messages
+    }
+
+    #[test]
+    fn parse_log_target() {
+        assert_eq!("syslog".parse(), Ok(LogTarget::Syslog));
+        assert_eq!("journald".parse(), Ok(LogTarget::Journald));
+        assert!("file".parse::<LogTarget>().is_err());
+    }
+
+    #[test]
+    fn send_rfc5424_messages() {
+        let (_dir, path, socket) = stand_in();
+        let mut log = SystemLog::connect(LogTarget::Syslog, &path, "app".into()).unwrap();
+
+        log.begin(2, Some(42)).unwrap();
+        log.write(Stream::Stdout, b"first\nsec").unwrap();
+        log.write(Stream::Stderr, b"oops\n").unwrap();
+        log.write(Stream::Stdout, b"ond\n").unwrap();
+        log.finish(&Exit::Code(0)).unwrap();
+
+        let messages = received(&socket);
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].starts_with("<14>1 "));
+        assert!(messages[0].contains(" app 42 - [cx@32473 attempt=\"2\"] "));
+        assert!(messages[0].ends_with("] first"));
+        assert!(messages[1].starts_with("<12>1 "));
+        assert!(messages[1].ends_with("] oops"));
+        assert!(messages[2].ends_with("] second"));
+    }
+
+    #[test]
+    fn send_journal_messages() {
+        let (_dir, path, socket) = stand_in();
+        let mut log = SystemLog::connect(LogTarget::Journald, &path, "app".into()).unwrap();
+
+        log.begin(3, None).unwrap();
+        log.write(Stream::Stderr, b"partial").unwrap();
+        log.finish(&Exit::Code(1)).unwrap();
+
+        assert_eq!(
+            received(&socket),
+            ["PRIORITY=4\nSYSLOG_IDENTIFIER=app\nCX_ATTEMPT=3\nMESSAGE=partial\n"]
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use super::*;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Bytes kept of a single line. Output without newlines would otherwise pile up in one line.
+const LINE_CAPACITY: usize = 4096;
+
+/// End of a line, within `LINE_CAPACITY`.
+#[derive(Debug, Default)]
+struct Line {
+    bytes: Vec<u8>,
+    /// bytes cut off the start of the line
+    dropped: usize,
+}
+
+impl Line {
+    fn extend(&mut self, data: &[u8]) {
+        self.bytes.extend_from_slice(data);
+
+        let excess = self.bytes.len().saturating_sub(LINE_CAPACITY);
+        self.bytes.drain(..excess);
+        self.dropped += excess;
+    }
+}
+
+impl std::fmt::Display for Line {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.dropped > 0 {
+            write!(f, "[cx: {} bytes dropped]", self.dropped)?;
+        }
+        write!(f, "{}", String::from_utf8_lossy(&self.bytes))
+    }
+}
+
+/// Last lines of one stream. A trailing line without a newline is kept as well.
+#[derive(Debug, Default)]
+struct Lines {
+    lines: VecDeque<Line>,
+    open: bool,
+}
+
+impl Lines {
+    fn push(&mut self, data: &[u8], capacity: usize) {
+        for chunk in data.split_inclusive(|b| *b == b'\n') {
+            let (line, complete) = match chunk.strip_suffix(b"\n") {
+                Some(line) => (line, true),
+                None => (chunk, false),
+            };
+
+            match (self.open, self.lines.back_mut()) {
+                (true, Some(last)) => last.extend(line),
+                _ => {
+                    let mut new = Line::default();
+                    new.extend(line);
+                    self.lines.push_back(new);
+                }
+            }
+            self.open = !complete;
+
+            while self.lines.len() > capacity {
+                self.lines.pop_front();
+            }
+        }
+    }
+
+    fn to_strings(&self) -> Vec<String> {
+        self.lines.iter().map(Line::to_string).collect()
+    }
+}
+
+/// Last lines written by an attempt.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct LastOutput {
+    pub attempt: usize,
+    pub stdout: Vec<String>,
+    pub stderr: Vec<String>,
+}
+
+impl LastOutput {
+    /// Block showing the lines of both streams between a header and a footer.
+    pub fn framed(&self, reason: &str) -> String {
+        use std::fmt::Write;
+
+        let mut framed = format!(
+            "cx: ---- last attempt output (attempt {}: {}) ----\n",
+            self.attempt, reason
+        );
+
+        for (stream, lines) in [
+            (Stream::Stdout, &self.stdout),
+            (Stream::Stderr, &self.stderr),
+        ] {
+            for line in lines {
+                let _ = writeln!(framed, "{} | {}", stream, line);
+            }
+        }
+
+        framed.push_str("cx: ---- end of last attempt output ----\n");
+        framed
+    }
+}
+
+#[derive(Debug, Default)]
+struct Buffers {
+    attempt: usize,
+    streams: [Lines; 2],
+}
+
+/// Ring buffer of the last `capacity` lines of stdout and stderr of the latest attempt. It is
+/// fed ahead of the `OutputLimit`, so that it holds the real end of the output even when the
+/// limit has cut it off.
+#[derive(Debug)]
+pub struct TailBuffer {
+    capacity: usize,
+    buffers: Mutex<Buffers>,
+}
+
+impl TailBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            buffers: Mutex::new(Buffers::default()),
+        }
+    }
+
+    fn buffers(&self) -> std::sync::MutexGuard<'_, Buffers> {
+        self.buffers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Starts over for the attempt.
+    pub(super) fn begin(&self, attempt: usize) {
+        *self.buffers() = Buffers {
+            attempt,
+            ..Default::default()
+        };
+    }
+
+    pub(super) fn push(&self, stream: Stream, data: &[u8]) {
+        self.buffers().streams[stream as usize].push(data, self.capacity);
+    }
+
+    /// Last lines of the latest attempt that was spawned, which is not necessarily the last
+    /// attempt made.
+    pub fn last_output(&self) -> LastOutput {
+        let buffers = self.buffers();
+
+        LastOutput {
+            attempt: buffers.attempt,
+            stdout: buffers.streams[Stream::Stdout as usize].to_strings(),
+            stderr: buffers.streams[Stream::Stderr as usize].to_strings(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_last_lines_per_stream() {
+        let buffer = TailBuffer::new(2);
+
+        buffer.begin(1);
+        buffer.push(Stream::Stdout, b"old\n");
+        buffer.begin(2);
+        buffer.push(Stream::Stdout, b"a\nb\nc");
+        buffer.push(Stream::Stderr, b"err\n");
+        buffer.push(Stream::Stdout, b"d\ne");
+
+        let expected = LastOutput {
+            attempt: 2,
+            stdout: vec!["cd".into(), "e".into()],
+            stderr: vec!["err".into()],
+        };
+        assert_eq!(buffer.last_output(), expected);
+    }
+
+    #[test]
+    fn keep_end_of_long_lines() {
+        let buffer = TailBuffer::new(2);
+
+        buffer.begin(1);
+        for _ in 0..3 {
+            buffer.push(Stream::Stdout, &[b'y'; LINE_CAPACITY]);
+        }
+        buffer.push(Stream::Stdout, b"end");
+
+        let stdout = buffer.last_output().stdout;
+        let expected = format!(
+            "[cx: {} bytes dropped]{}end",
+            2 * LINE_CAPACITY + 3,
+            "y".repeat(LINE_CAPACITY - 3)
+        );
+        assert_eq!(stdout, [expected]);
+    }
+
+    #[test]
+    fn frame_last_output() {
+        let output = LastOutput {
+            attempt: 3,
+            stdout: vec!["out".into()],
+            stderr: vec!["err".into()],
+        };
+
+        assert_eq!(
+            output.framed("exited with code 1"),
+            "cx: ---- last attempt output (attempt 3: exited with code 1) ----\n\
+             stdout | out\n\
+             stderr | err\n\
+             cx: ---- end of last attempt output ----\n"
+        );
+    }
+}
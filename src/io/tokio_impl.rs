@@ -1,62 +1,225 @@
 use super::*;
+use std::time::Duration;
 
-pub struct PipedCmdExecutor;
+/// Signals the child once it has run for `after`, and kills it when it is still running
+/// `kill_after` later.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeout {
+    pub after: Duration,
+    pub signal: Signal,
+    pub kill_after: Option<Duration>,
+}
+
+#[derive(new, Default)]
+pub struct PipedCmdExecutor {
+    options: SpawnOptions,
+    output: std::sync::Arc<Output>,
+}
+
+/// Where signals to the child go: the child itself, or its whole process group when it runs in
+/// one.
+struct Target<'a> {
+    child: &'a mut tokio::process::Child,
+    #[cfg(unix)]
+    group: Option<process_group::ProcessGroup>,
+}
+
+impl Target<'_> {
+    #[cfg(unix)]
+    fn signal(&mut self, signal: Signal) -> std::io::Result<()> {
+        if let Some(group) = &self.group {
+            return group.signal(signal.0);
+        }
+
+        match self.child.id() {
+            Some(pid) => match unsafe { libc::kill(pid as libc::pid_t, signal.0) } {
+                0 => Ok(()),
+                _ => Err(std::io::Error::last_os_error()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn signal(&mut self, _: Signal) -> std::io::Result<()> {
+        self.child.start_kill()
+    }
+
+    fn kill(&mut self) -> std::io::Result<()> {
+        #[cfg(unix)]
+        if let Some(group) = &self.group {
+            return group.signal(libc::SIGKILL);
+        }
+
+        self.child.start_kill()
+    }
+}
 
 impl PipedCmdExecutor {
-    fn parse_command(command: &str) -> (String, Vec<String>) {
-        let mut elements = command.split(' ').map(Into::into).collect::<Vec<_>>();
+    /// Waits for the child to exit, telling whether it ran into the timeout.
+    async fn wait(
+        &self,
+        target: &mut Target<'_>,
+        timeout: Option<&Timeout>,
+    ) -> std::io::Result<(std::process::ExitStatus, bool)> {
+        let expired = async {
+            match timeout {
+                Some(timeout) => {
+                    tokio::time::sleep(timeout.after).await;
+                    timeout
+                }
+                None => std::future::pending().await,
+            }
+        };
 
-        let options = elements.drain(1..).collect::<Vec<_>>();
+        let timeout = tokio::select! {
+            status = target.child.wait() => return Ok((status?, false)),
+            _ = self.output.limit_exceeded() => {
+                target.kill()?;
+                return Ok((target.child.wait().await?, false));
+            }
+            timeout = expired => timeout,
+        };
 
-        let program = match elements.first() {
-            Some(_) => elements.remove(0),
-            None => String::new(),
+        target.signal(timeout.signal)?;
+        // A stopped child would not act on the signal until continued.
+        #[cfg(unix)]
+        let _ = target.signal(Signal(libc::SIGCONT));
+
+        let kill_after = match timeout.kill_after {
+            Some(kill_after) => kill_after,
+            None => return Ok((target.child.wait().await?, true)),
         };
 
-        (program, options)
+        tokio::select! {
+            status = target.child.wait() => return Ok((status?, true)),
+            _ = tokio::time::sleep(kill_after) => (),
+        }
+
+        target.kill()?;
+        Ok((target.child.wait().await?, true))
     }
-}
 
-#[async_trait::async_trait]
-impl PipedCmdExecute for PipedCmdExecutor {
-    async fn piped_exec(&self, command: &str) -> std::io::Result<Exit> {
+    async fn run(
+        &self,
+        command: &str,
+        attempt: usize,
+        timeout: Option<&Timeout>,
+    ) -> std::io::Result<TimedExit> {
         let (program, options) = Self::parse_command(command);
 
-        let mut child = tokio::process::Command::new(program)
+        let mut command = tokio::process::Command::new(program);
+        command
             .args(options)
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped())
-            .spawn()?;
+            .kill_on_drop(self.options.kill_on_drop);
+
+        #[cfg(unix)]
+        if !self.options.is_empty() {
+            let options = self.options.clone();
+            unsafe { command.pre_exec(move || options.apply()) };
+        }
 
-        let mut child_stdout = child.stdout.take().ok_or_else(|| {
+        let mut child = command.spawn()?;
+        let pid = child.id();
+
+        let child_stdout = child.stdout.take().ok_or_else(|| {
             std::io::Error::new(std::io::ErrorKind::Other, "failed to take stdout")
         })?;
 
-        let mut child_stderr = child.stderr.take().ok_or_else(|| {
+        let child_stderr = child.stderr.take().ok_or_else(|| {
             std::io::Error::new(std::io::ErrorKind::Other, "failed to take stderr")
         })?;
 
-        let mut process_stdout = tokio::io::stdout();
+        let mut target = Target {
+            child: &mut child,
+            #[cfg(unix)]
+            group: pid
+                .filter(|_| self.options.process_group)
+                .map(|pid| process_group::ProcessGroup::register(pid, self.options.kill_on_drop)),
+        };
 
-        let mut process_stderr = tokio::io::stderr();
+        self.output.begin(attempt, pid).await;
 
         let (exit_status, _, _) = tokio::join!(
-            child.wait(),
-            tokio::io::copy(&mut child_stdout, &mut process_stdout),
-            tokio::io::copy(&mut child_stderr, &mut process_stderr)
+            self.wait(&mut target, timeout),
+            forward(child_stdout, Stream::Stdout, &self.output),
+            forward(child_stderr, Stream::Stderr, &self.output)
         );
 
-        let code = exit_status?.code().ok_or_else(|| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "failed to start child process or terminated abnormally",
-            )
-        })?;
+        let (exit_status, timed_out) = exit_status?;
+        let exit = exit_from_status(exit_status)?;
+        self.output.finish(&exit).await;
 
-        Ok(Exit { code })
+        #[cfg(unix)]
+        if let Some(group) = target.group {
+            group.release();
+        }
+
+        Ok(TimedExit { exit, timed_out })
+    }
+
+    pub(super) fn parse_command(command: &str) -> (String, Vec<String>) {
+        let mut elements = command.split(' ').map(Into::into).collect::<Vec<_>>();
+
+        let options = elements.drain(1..).collect::<Vec<_>>();
+
+        let program = match elements.first() {
+            Some(_) => elements.remove(0),
+            None => String::new(),
+        };
+
+        (program, options)
     }
 }
 
+#[async_trait::async_trait]
+impl PipedCmdExecute for PipedCmdExecutor {
+    async fn piped_exec(&self, command: &str, attempt: usize) -> std::io::Result<Exit> {
+        Ok(self.run(command, attempt, None).await?.exit)
+    }
+}
+
+#[async_trait::async_trait]
+impl TimedCmdExecute for PipedCmdExecutor {
+    async fn timed_exec(&self, command: &str, timeout: &Timeout) -> std::io::Result<TimedExit> {
+        self.run(command, 1, Some(timeout)).await
+    }
+}
+
+pub(super) fn exit_from_status(status: std::process::ExitStatus) -> std::io::Result<Exit> {
+    if let Some(code) = status.code() {
+        return Ok(Exit::Code(code));
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return Ok(Exit::Signal {
+                signal,
+                core_dumped: status.core_dumped(),
+            });
+        }
+    }
+
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "failed to start child process or terminated abnormally",
+    ))
+}
+
+/// Replaces the current process with the command, returning only when that fails.
+#[cfg(unix)]
+pub fn exec(command: &str) -> std::io::Error {
+    use std::os::unix::process::CommandExt;
+
+    let (program, options) = PipedCmdExecutor::parse_command(command);
+    std::process::Command::new(program).args(options).exec()
+}
+
 pub struct Sleeper;
 
 #[async_trait::async_trait]
@@ -66,29 +229,197 @@ impl Sleep for Sleeper {
     }
 }
 
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::Utc::now()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[tokio::test]
     async fn should_success_given_suitable_command() {
-        let actual = PipedCmdExecutor.piped_exec("echo abcd").await.unwrap();
-        let expected = Exit { code: 0 };
+        let actual = PipedCmdExecutor::default()
+            .piped_exec("echo abcd", 1)
+            .await
+            .unwrap();
+        let expected = Exit::Code(0);
         assert_eq!(actual, expected);
     }
 
     #[tokio::test]
     async fn should_failure_when_command_not_found() {
-        let actual = PipedCmdExecutor.piped_exec("failed").await.is_err();
+        let actual = PipedCmdExecutor::default()
+            .piped_exec("failed", 1)
+            .await
+            .is_err();
         assert!(actual);
     }
 
     #[tokio::test]
     async fn should_success_when_exit_not_zero() {
-        let actual = PipedCmdExecutor
-            .piped_exec("cat non_existent_file")
+        let actual = PipedCmdExecutor::default()
+            .piped_exec("cat non_existent_file", 1)
             .await
             .unwrap();
-        assert_ne!(actual, Exit { code: 0 });
+        assert_ne!(actual, Exit::Code(0));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn should_report_exceeded_cpu_limit() {
+        let limits = ResourceLimits {
+            cpu_time: Some(1),
+            ..Default::default()
+        };
+        let options = SpawnOptions {
+            limits,
+            ..Default::default()
+        };
+        let actual = PipedCmdExecutor::new(options, Default::default())
+            .piped_exec("sha256sum /dev/zero", 1)
+            .await
+            .unwrap();
+        assert!(actual.limit_exceeded());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn should_report_signal_termination() {
+        let actual = PipedCmdExecutor::default()
+            .piped_exec("sh -c kill$IFS-KILL$IFS$$", 1)
+            .await
+            .unwrap();
+        let expected = Exit::Signal {
+            signal: libc::SIGKILL,
+            core_dumped: false,
+        };
+        assert_eq!(actual, expected);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn should_kill_when_output_exceeds_limit() {
+        let limit = OutputLimit {
+            per_attempt: Some(1024),
+            per_run: None,
+            policy: LimitPolicy::Kill,
+        };
+        let output = Output::new(vec![]).with_limit(limit);
+        let actual = PipedCmdExecutor::new(Default::default(), std::sync::Arc::new(output))
+            .piped_exec("yes", 1)
+            .await
+            .unwrap();
+        let expected = Exit::Signal {
+            signal: libc::SIGKILL,
+            core_dumped: false,
+        };
+        assert_eq!(actual, expected);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn should_signal_when_timed_out() {
+        let timeout = Timeout {
+            after: Duration::from_millis(50),
+            signal: Signal(libc::SIGTERM),
+            kill_after: None,
+        };
+        let actual = PipedCmdExecutor::default()
+            .timed_exec("sleep 5", &timeout)
+            .await
+            .unwrap();
+        let expected = TimedExit {
+            exit: Exit::Signal {
+                signal: libc::SIGTERM,
+                core_dumped: false,
+            },
+            timed_out: true,
+        };
+        assert_eq!(actual, expected);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn should_kill_when_signal_is_ignored() {
+        let timeout = Timeout {
+            after: Duration::from_millis(50),
+            signal: Signal(libc::SIGCONT),
+            kill_after: Some(Duration::from_millis(50)),
+        };
+        let actual = PipedCmdExecutor::default()
+            .timed_exec("sleep 5", &timeout)
+            .await
+            .unwrap();
+        let expected = Exit::Signal {
+            signal: libc::SIGKILL,
+            core_dumped: false,
+        };
+        assert_eq!(actual.exit, expected);
+    }
+
+    #[tokio::test]
+    async fn should_not_time_out_when_finished_in_time() {
+        let timeout = Timeout {
+            after: Duration::from_secs(5),
+            signal: Signal(15),
+            kill_after: None,
+        };
+        let actual = PipedCmdExecutor::default()
+            .timed_exec("echo abcd", &timeout)
+            .await
+            .unwrap();
+        let expected = TimedExit {
+            exit: Exit::Code(0),
+            timed_out: false,
+        };
+        assert_eq!(actual, expected);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn should_kill_process_group_when_cancelled() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("spawn.sh");
+        let pid_file = dir.path().join("pid");
+        std::fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\nsleep 30 &\necho $! > {}\nwait\n",
+                pid_file.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let options = SpawnOptions {
+            process_group: true,
+            kill_on_drop: true,
+            ..Default::default()
+        };
+        let executor = PipedCmdExecutor::new(options, Default::default());
+        let command = script.display().to_string();
+        let exec = executor.piped_exec(&command, 1);
+        let _ = tokio::time::timeout(Duration::from_millis(500), exec).await;
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        let stat = format!("/proc/{}/stat", pid.trim());
+        let alive = || match std::fs::read_to_string(&stat) {
+            Ok(stat) => !stat.contains(") Z "),
+            Err(_) => false,
+        };
+        for _ in 0..50 {
+            if !alive() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(!alive());
     }
 }
@@ -27,39 +27,732 @@ mod io;
 use app::*;
 use config::*;
 use io::*;
+use std::sync::Arc;
+
+fn exit_with_error(message: impl std::fmt::Display) -> ! {
+    eprintln!("cx: {}", message);
+    std::process::exit(1);
+}
+
+fn spawn_options(options: &ExecOptions) -> SpawnOptions {
+    let limits = ResourceLimits {
+        address_space: options.limit_as,
+        cpu_time: options.limit_cpu,
+        open_files: options.limit_nofile,
+        processes: options.limit_nproc,
+        core_size: options.limit_core,
+    };
+
+    #[cfg(unix)]
+    {
+        let credentials = match (options.user.as_deref(), options.group.as_deref()) {
+            (None, None) => None,
+            (user, group) => {
+                if !Credentials::privileged() {
+                    exit_with_error("--user and --group require cx to run as root");
+                }
+                Some(Credentials::resolve(user, group).unwrap_or_else(|e| exit_with_error(e)))
+            }
+        };
+
+        // Killing the command when it outputs too much takes along what it has started, which
+        // may keep the pipes open.
+        let kill_on_limit = options.output_limit_policy == LimitPolicy::Kill
+            && (options.max_output_bytes.is_some() || options.max_run_output_bytes.is_some());
+
+        SpawnOptions {
+            limits,
+            credentials,
+            process_group: kill_on_limit,
+            kill_on_drop: false,
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let spawn = SpawnOptions {
+            limits,
+            kill_on_drop: false,
+        };
+        if !spawn.is_empty() || options.user.is_some() || options.group.is_some() {
+            exit_with_error("--limit-*, --user and --group are not supported on this platform");
+        }
+
+        spawn
+    }
+}
+
+fn event_log(target: &Option<EventsTarget>) -> Option<Arc<EventLog>> {
+    target.as_ref().map(|target| {
+        let events = EventLog::open(target).unwrap_or_else(|e| exit_with_error(e));
+        Arc::new(events)
+    })
+}
+
+#[cfg(unix)]
+fn system_log(command: &str, target: LogTarget, options: &ExecOptions) -> Box<dyn OutputSink> {
+    let socket = options
+        .log_socket
+        .clone()
+        .unwrap_or_else(|| target.default_socket().into());
+    let identifier = options.log_identifier.clone().unwrap_or_else(|| {
+        let program = command.split(' ').next().unwrap_or("");
+        let name = std::path::Path::new(program).file_name();
+        name.map_or("cx".into(), |name| name.to_string_lossy().into_owned())
+    });
+
+    let log = SystemLog::connect(target, &socket, identifier)
+        .unwrap_or_else(|e| exit_with_error(format!("{}: {}", socket.display(), e)));
+    Box::new(log)
+}
+
+#[cfg(not(unix))]
+fn system_log(_: &str, _: LogTarget, _: &ExecOptions) -> Box<dyn OutputSink> {
+    exit_with_error("--log-to is not supported on this platform")
+}
+
+fn tail(options: &ExecOptions) -> Option<Arc<TailBuffer>> {
+    options
+        .tail_lines
+        .map(|lines| Arc::new(TailBuffer::new(lines)))
+}
+
+fn artifacts(options: &ExecOptions) -> Option<Artifacts> {
+    options.artifacts_dir.as_ref().map(|dir| {
+        Artifacts::create(dir.clone())
+            .unwrap_or_else(|e| exit_with_error(format!("{}: {}", dir.display(), e)))
+    })
+}
+
+fn output(
+    command: &str,
+    options: &ExecOptions,
+    tail: &Option<Arc<TailBuffer>>,
+    artifacts: &Option<Artifacts>,
+    history: Arc<History>,
+) -> Arc<Output> {
+    let mut terminal: Box<dyn OutputSink> = Box::new(Terminal::new(options.prefix.clone()));
+    if options.collapse_repeats {
+        terminal = Box::new(Deduplicated::new(terminal));
+    }
+
+    let mut sinks: Vec<Box<dyn OutputSink>> = match options.output {
+        OutputMode::All => vec![terminal],
+        mode => vec![Box::new(Captured::new(terminal, mode))],
+    };
+
+    let rotation = Rotation {
+        max_size: options.log_max_size,
+        period: options.log_rotate,
+        keep: options.log_keep,
+        compress: options.log_compress,
+    };
+
+    let logs = match (&options.log_file, &options.stderr_log) {
+        (Some(path), None) => vec![(path, None)],
+        (log_file, stderr_log) => log_file
+            .iter()
+            .map(|path| (path, Some(Stream::Stdout)))
+            .chain(stderr_log.iter().map(|path| (path, Some(Stream::Stderr))))
+            .collect(),
+    };
+
+    for (path, stream) in logs {
+        let log = LogFile::open(
+            path.clone(),
+            stream,
+            rotation.clone(),
+            options.prefix.clone(),
+        )
+        .unwrap_or_else(|e| exit_with_error(format!("{}: {}", path.display(), e)));
+        sinks.push(Box::new(log));
+    }
+
+    if let Some(artifacts) = artifacts {
+        sinks.push(Box::new(artifacts.files()));
+    }
+
+    if let Some(target) = options.log_to {
+        sinks.push(system_log(command, target, options));
+    }
+
+    let limit = OutputLimit {
+        per_attempt: options.max_output_bytes,
+        per_run: options.max_run_output_bytes,
+        policy: options.output_limit_policy,
+    };
+
+    let output = Output::new(sinks)
+        .with_limit(limit)
+        .with_tail(tail.clone())
+        .with_observer(history);
+    Arc::new(output)
+}
+
+fn executor(options: ExecOptions, output: Arc<Output>) -> Box<dyn PipedCmdExecute + Send + Sync> {
+    let spawn = spawn_options(&options);
+
+    if !options.pty {
+        return Box::new(PipedCmdExecutor::new(spawn, output));
+    }
+
+    #[cfg(unix)]
+    return Box::new(PtyCmdExecutor::new(spawn, output));
+
+    #[cfg(not(unix))]
+    exit_with_error("--pty is not supported on this platform")
+}
+
+fn print_last_output(history: &History, tail: &Option<Arc<TailBuffer>>) {
+    if let Some(tail) = tail {
+        let last_output = tail.last_output();
+
+        if let Some(attempt) = history.attempt(last_output.attempt) {
+            eprint!("{}", last_output.framed(&attempt.reason()));
+        }
+    }
+}
+
+fn print_summary(history: &History, format: Option<SummaryFormat>, tail: &Option<Arc<TailBuffer>>) {
+    if let Some(format) = format {
+        let last_output = tail.as_ref().map(|tail| tail.last_output());
+        eprint!("{}", history.summary(format, last_output));
+    }
+}
+
+/// Reads the file, or stdin when it is omitted or "-".
+fn read_input(file: Option<std::path::PathBuf>) -> String {
+    use std::io::Read;
+
+    match file {
+        Some(path) if path.as_os_str() != "-" => std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| exit_with_error(format!("{}: {}", path.display(), e))),
+        _ => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .unwrap_or_else(|e| exit_with_error(format!("stdin: {}", e)));
+            text
+        }
+    }
+}
+
+fn parallel_jobs(file: Option<std::path::PathBuf>, command: &[String]) -> Vec<Job> {
+    match (file, command.is_empty()) {
+        (None, false) => Job::from_args(command),
+        (file, _) => Job::from_lines(&read_input(file)),
+    }
+}
+
+fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+}
+
+#[cfg(unix)]
+fn exec(command: &str) -> ! {
+    let error = anyhow::Error::from(io::exec(command));
+    eprintln!("cx: {}", diagnose(command, &error));
+    std::process::exit(spawn_error_status(&error));
+}
+
+#[cfg(not(unix))]
+fn exec(_: &str) -> ! {
+    exit_with_error("running a command in place of cx is not supported on this platform")
+}
+
+/// Takes the lock or exits, with LOCK_BUSY when someone else still holds it.
+#[cfg(unix)]
+async fn lock(
+    path: &std::path::Path,
+    wait: Option<std::time::Duration>,
+    command: &str,
+) -> FileLock {
+    match FileLock::acquire(path, wait, command).await {
+        Ok(lock) => lock,
+        Err(LockError::Busy(holder)) => {
+            match holder {
+                Some(holder) => eprintln!("cx: {} is held by {}", path.display(), holder),
+                None => eprintln!("cx: {} is held by another process", path.display()),
+            }
+            std::process::exit(LOCK_BUSY);
+        }
+        Err(LockError::Io(e)) => exit_with_error(format!("{}: {}", path.display(), e)),
+    }
+}
+
+#[cfg(not(unix))]
+async fn lock(_: &std::path::Path, _: Option<std::time::Duration>, _: &str) {
+    exit_with_error("locking is not supported on this platform")
+}
+
+/// Takes the lock of `--lock`, if any, for the rest of the run.
+#[cfg(unix)]
+async fn exec_lock(options: &ExecOptions, command: &str) -> Option<FileLock> {
+    match &options.lock {
+        Some(path) => Some(lock(path, options.lock_wait, command).await),
+        None => None,
+    }
+}
+
+#[cfg(not(unix))]
+async fn exec_lock(options: &ExecOptions, command: &str) {
+    if let Some(path) = &options.lock {
+        lock(path, options.lock_wait, command).await
+    }
+}
+
+/// What retry, supervise, fallback and cron set up from their `ExecOptions` to run a command.
+struct Execution {
+    /// held for the rest of the run
+    #[cfg(unix)]
+    _lock: Option<FileLock>,
+    tail: Option<Arc<TailBuffer>>,
+    summary: Option<SummaryFormat>,
+    output: Arc<Output>,
+    history: Arc<History>,
+    executor: Box<dyn PipedCmdExecute + Send + Sync>,
+}
+
+impl Execution {
+    async fn start(command: &str, options: ExecOptions) -> Self {
+        #[cfg(unix)]
+        let lock = exec_lock(&options, command).await;
+        #[cfg(not(unix))]
+        exec_lock(&options, command).await;
+
+        let tail = tail(&options);
+        let artifacts = artifacts(&options);
+        let history = History::new(event_log(&options.events)).with_artifacts(artifacts.clone());
+        let history = Arc::new(history);
+        let output = output(command, &options, &tail, &artifacts, history.clone());
+
+        Self {
+            #[cfg(unix)]
+            _lock: lock,
+            tail,
+            summary: options.summary,
+            history,
+            executor: executor(options, output.clone()),
+            output,
+        }
+    }
+
+    /// Waits for the output to be written, then prints the last output when the run failed and
+    /// the summary.
+    async fn finish(&self, failed: bool) {
+        self.output.close().await;
+        if failed {
+            print_last_output(&self.history, &self.tail);
+        }
+        print_summary(&self.history, self.summary, &self.tail);
+    }
+}
 
 #[tokio::main]
 async fn main() {
     use structopt::StructOpt;
 
     let config = Config::from_args();
-    let executor = PipedCmdExecutor;
-    let sleeper = Sleeper;
 
     let exit_code = match config {
         Config::retry {
             command,
             count,
             interval,
+            exec,
         } => {
             let command = command.join(" ");
-            let app = RetryApp::new(&command, count, interval, &executor, &sleeper);
-            let output = run(app).await;
-            match output {
-                RetryResult::Success => 0,
-                RetryResult::Failure => 1,
+            let execution = Execution::start(&command, exec).await;
+            let history = &execution.history;
+            let app = RetryApp::new(
+                &command,
+                count,
+                interval,
+                execution.executor.as_ref(),
+                &Sleeper,
+                history,
+            );
+            let result = run(app).await;
+            execution
+                .finish(matches!(result, RetryResult::Failure))
+                .await;
+            match result {
+                RetryResult::Success => {
+                    history.succeeded();
+                    0
+                }
+                RetryResult::Failure => {
+                    history.gave_up();
+                    1
+                }
             }
         }
         Config::supervise {
             command,
             count,
             interval,
+            exec,
         } => {
             let command = command.join(" ");
-            let app = SuperviseApp::new(&command, count, interval, &executor, &sleeper);
+            let execution = Execution::start(&command, exec).await;
+            let history = &execution.history;
+            let app = SuperviseApp::new(
+                &command,
+                count,
+                interval,
+                execution.executor.as_ref(),
+                &Sleeper,
+                history,
+            );
             run(app).await;
+            execution.finish(false).await;
+            history.stopped();
+            0
+        }
+        Config::parallel {
+            jobs,
+            halt,
+            retry,
+            interval,
+            file,
+            command,
+        } => {
+            let app = ParallelApp::new(
+                RetryJobs::new(retry + 1, interval),
+                parallel_jobs(file, &command),
+                jobs.unwrap_or_else(default_jobs),
+                halt,
+            );
+            let local = tokio::task::LocalSet::new();
+            match local.run_until(run(app)).await {
+                ParallelResult::Success => 0,
+                ParallelResult::Failure => 1,
+            }
+        }
+        Config::map {
+            jobs,
+            retry,
+            interval,
+            file,
+            results,
+            resume,
+            failed,
+            command,
+        } => {
+            let template = command.join(" ");
+            if template.is_empty() {
+                exit_with_error("no command entered");
+            }
+            let (results, succeeded) = match &results {
+                Some(path) => {
+                    let (results, succeeded) = Results::open(path, resume)
+                        .unwrap_or_else(|e| exit_with_error(format!("{}: {}", path.display(), e)));
+                    (Some(results), succeeded)
+                }
+                None => (None, Default::default()),
+            };
+            let input = read_input(file);
+            // Created only after reading the input, which it may be the same file as.
+            let failed = failed.map(|path| {
+                FailedItems::create(&path)
+                    .unwrap_or_else(|e| exit_with_error(format!("{}: {}", path.display(), e)))
+            });
+            let (done, items): (Vec<_>, Vec<_>) = input
+                .lines()
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .partition(|item| succeeded.contains(*item));
+            if !done.is_empty() {
+                eprintln!("cx: skipping {} items that already succeeded", done.len());
+            }
+            let items = items
+                .into_iter()
+                .map(|item| Job::from_template(&template, item))
+                .collect();
+            let app = ParallelApp::new(
+                MapJobs::new(RetryJobs::new(retry + 1, interval), results, failed),
+                items,
+                jobs.unwrap_or_else(default_jobs),
+                Halt::Never,
+            );
+            let local = tokio::task::LocalSet::new();
+            match local.run_until(run(app)).await {
+                ParallelResult::Success => 0,
+                ParallelResult::Failure => 1,
+            }
+        }
+        Config::fallback {
+            count,
+            interval,
+            exec,
+            command,
+        } => {
+            let alternatives = Alternative::from_args(&command, count, interval)
+                .unwrap_or_else(|e| exit_with_error(e));
+            let command = match alternatives.first() {
+                Some(first) => first.command.clone(),
+                None => exit_with_error("no command entered"),
+            };
+            let execution = Execution::start(&command, exec).await;
+            let history = &execution.history;
+            let executor = execution.executor.as_ref();
+            let app = FallbackApp::new(&alternatives, executor, &Sleeper, history);
+            let result = run(app).await;
+            execution
+                .finish(matches!(result, FallbackResult::Failure))
+                .await;
+            match result {
+                FallbackResult::Success(_) => {
+                    history.succeeded();
+                    0
+                }
+                FallbackResult::Failure => {
+                    history.gave_up();
+                    1
+                }
+            }
+        }
+        Config::race {
+            all_output,
+            command,
+        } => {
+            let jobs = Job::from_args(&command);
+            let app = RaceApp::new(RaceJobs::new(&jobs, all_output), jobs);
+            let local = tokio::task::LocalSet::new();
+            match local.run_until(run(app)).await {
+                RaceResult::Won => 0,
+                RaceResult::Lost(status) => status,
+            }
+        }
+        Config::cron {
+            schedule,
+            timezone,
+            missed,
+            count,
+            exec,
+            command,
+        } => {
+            let command = command.join(" ");
+            let execution = Execution::start(&command, exec).await;
+            let history = &execution.history;
+            let cron = Cron {
+                schedule,
+                timezone,
+                missed,
+            };
+            let app = CronApp::new(
+                &command,
+                cron,
+                count,
+                execution.executor.as_ref(),
+                &Sleeper,
+                &SystemClock,
+                history,
+            );
+            let result = run(app).await;
+            execution.finish(false).await;
+            history.stopped();
+            if let Err(e) = result {
+                exit_with_error(e);
+            }
             0
         }
+        Config::wait_for {
+            conditions,
+            any,
+            interval,
+            timeout,
+            command,
+        } => {
+            // A probe cut off by the timeout is killed along with what it has started.
+            let options = SpawnOptions {
+                #[cfg(unix)]
+                process_group: true,
+                kill_on_drop: true,
+                ..Default::default()
+            };
+            let executor = PipedCmdExecutor::new(options, Default::default());
+            let app = WaitForApp::new(&conditions, any, interval, timeout, &executor, &Sleeper);
+            match run(app).await {
+                WaitForResult::Ready if command.is_empty() => 0,
+                WaitForResult::Ready => exec(&command.join(" ")),
+                WaitForResult::TimedOut(unmet) => {
+                    let unmet = unmet.iter().map(Condition::to_string).collect::<Vec<_>>();
+                    eprintln!("cx: timed out waiting for {}", unmet.join(", "));
+                    TIMED_OUT
+                }
+                WaitForResult::SpawnFailed(e) => spawn_error_status(&e),
+            }
+        }
+        Config::bench {
+            runs,
+            warmup,
+            show_output,
+            export_json,
+            export_csv,
+            events,
+            command,
+        } => {
+            let commands = Job::from_args(&command);
+            if commands.is_empty() {
+                exit_with_error("no command entered");
+            }
+            if runs == 0 {
+                exit_with_error("--runs must be at least 1");
+            }
+            let sinks = || -> Vec<Box<dyn OutputSink>> {
+                match show_output {
+                    true => vec![Box::new(Terminal::new(None))],
+                    false => vec![],
+                }
+            };
+            let events = event_log(&events);
+            let mut benchmarks = Vec::new();
+            for job in &commands {
+                let history = Arc::new(History::new(events.clone()));
+                let output = Arc::new(Output::new(sinks()).with_observer(history.clone()));
+                let executor = PipedCmdExecutor::new(SpawnOptions::default(), output.clone());
+                let executor = CpuTimed::new(&executor);
+                let app = SuperviseApp::new(
+                    &job.command,
+                    Some(warmup + runs),
+                    0.0,
+                    &executor,
+                    &Sleeper,
+                    &history,
+                );
+                run(app).await;
+                output.close().await;
+                history.stopped();
+                let attempts = history.attempts();
+                if let Some(benchmark) =
+                    Benchmark::from_attempts(&job.command, &attempts, &executor.times(), warmup)
+                {
+                    print!("{}", benchmark.text());
+                    benchmarks.push(benchmark);
+                }
+            }
+            print!("{}", comparison(&benchmarks));
+            let export = |path: Option<std::path::PathBuf>, text: String| {
+                if let Some(path) = path {
+                    std::fs::write(&path, text)
+                        .unwrap_or_else(|e| exit_with_error(format!("{}: {}", path.display(), e)));
+                }
+            };
+            export(export_json, to_json(&benchmarks));
+            export(export_csv, to_csv(&benchmarks));
+            match benchmarks.len() == commands.len()
+                && benchmarks.iter().all(|benchmark| benchmark.failed == 0)
+            {
+                true => 0,
+                false => 1,
+            }
+        }
+        Config::flaky {
+            runs,
+            min_runs,
+            threshold,
+            interval,
+            failures_dir,
+            show_output,
+            events,
+            command,
+        } => {
+            let command = command.join(" ");
+            if command.is_empty() {
+                exit_with_error("no command entered");
+            }
+            if runs == 0 {
+                exit_with_error("--runs must be at least 1");
+            }
+            let temporary = failures_dir.is_none();
+            let dir = failures_dir.unwrap_or_else(|| {
+                tempfile::Builder::new()
+                    .prefix("cx-flaky-")
+                    .tempdir()
+                    .unwrap_or_else(|e| exit_with_error(e))
+                    .into_path()
+            });
+            let artifacts = Artifacts::create(dir.clone())
+                .unwrap_or_else(|e| exit_with_error(format!("{}: {}", dir.display(), e)))
+                .failures_only();
+            let mut sinks: Vec<Box<dyn OutputSink>> = vec![Box::new(artifacts.files())];
+            if show_output {
+                sinks.push(Box::new(Terminal::new(None)));
+            }
+            let history =
+                Arc::new(History::new(event_log(&events)).with_artifacts(Some(artifacts)));
+            let output = Arc::new(Output::new(sinks).with_observer(history.clone()));
+            let executor = PipedCmdExecutor::new(SpawnOptions::default(), output.clone());
+            let sampling = Sampling {
+                runs,
+                min_runs,
+                threshold,
+            };
+            let app = FlakyApp::new(&command, sampling, interval, &executor, &Sleeper, &history);
+            let flakiness = run(app).await;
+            output.close().await;
+            history.stopped();
+            print!("{}", flakiness.text());
+            if flakiness.passed < flakiness.runs {
+                eprintln!("cx: output of failing runs saved in {}", dir.display());
+            } else if temporary {
+                let _ = std::fs::remove_dir(&dir);
+            }
+            match flakiness.failure_rate() > threshold {
+                true => 1,
+                false => 0,
+            }
+        }
+        Config::lock {
+            path,
+            wait,
+            no_wait,
+            command,
+        } => {
+            let command = command.join(" ");
+            if command.is_empty() {
+                exit_with_error("no command entered");
+            }
+            let wait = if no_wait {
+                Some(Default::default())
+            } else {
+                wait
+            };
+            let _lock = lock(&path, wait, &command).await;
+            #[cfg(unix)]
+            if let Err(e) = _lock.inherit() {
+                exit_with_error(format!("{}: {}", path.display(), e));
+            }
+            exec(&command)
+        }
+        Config::timeout {
+            duration,
+            signal,
+            kill_after,
+            preserve_status,
+            foreground,
+            command,
+        } => {
+            let command = command.join(" ");
+            let output = Arc::new(Output::new(vec![Box::new(Terminal::new(None))]));
+            let timeout = Timeout {
+                after: duration,
+                signal,
+                kill_after,
+            };
+            // The whole process group is signalled, reaching what the command has started. Being
+            // in the background, the group cannot read from the terminal though.
+            let options = SpawnOptions {
+                #[cfg(unix)]
+                process_group: !foreground,
+                ..Default::default()
+            };
+            let executor = PipedCmdExecutor::new(options, output.clone());
+            let result = run(TimeoutApp::new(&command, timeout, &executor)).await;
+            output.close().await;
+            result.code(preserve_status)
+        }
     };
 
     std::process::exit(exit_code);
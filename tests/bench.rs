@@ -0,0 +1,57 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+#[test]
+fn report_timing_of_runs() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("bench")
+        .arg("-n")
+        .arg("3")
+        .arg("--warmup")
+        .arg("1")
+        .arg("--")
+        .arg("echo")
+        .arg("abcd")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::starts_with("Benchmark 'echo abcd' (3 runs)\n")
+                .and(predicate::str::contains("p99"))
+                .and(predicate::str::contains("abcd\n").not()),
+        );
+}
+
+#[test]
+fn compare_and_export_results() {
+    let dir = tempfile::tempdir().unwrap();
+    let json = dir.path().join("bench.json");
+    let csv = dir.path().join("bench.csv");
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("bench")
+        .arg("-n")
+        .arg("2")
+        .arg("--export-json")
+        .arg(&json)
+        .arg("--export-csv")
+        .arg(&csv)
+        .arg("--")
+        .arg("true")
+        .arg(":::")
+        .arg("echo")
+        .arg("abcd")
+        .arg(":::")
+        .arg("false")
+        .assert()
+        .code(1)
+        .stdout(
+            predicate::str::contains("times faster than")
+                .and(predicate::str::contains("faster than 'false'").not()),
+        );
+
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+    assert_eq!(json["results"][2]["failed"], 2);
+    assert_eq!(std::fs::read_to_string(&csv).unwrap().lines().count(), 4);
+}
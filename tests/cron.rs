@@ -0,0 +1,33 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+#[test]
+fn reject_invalid_expression() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("cron")
+        .arg("*/5 * * *")
+        .arg("--")
+        .arg("echo")
+        .arg("abcd")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected 5 fields, got 4"));
+}
+
+#[test]
+fn fail_when_schedule_never_matches() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("cron")
+        .arg("0 0 30 2 *")
+        .arg("--timezone")
+        .arg("utc")
+        .arg("--")
+        .arg("echo")
+        .arg("abcd")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("does not match any time"));
+}
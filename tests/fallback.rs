@@ -0,0 +1,80 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+#[test]
+fn report_alternative_that_succeeded() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("fallback")
+        .arg("--")
+        .arg("cat")
+        .arg("cx-non-existent-file")
+        .arg(":::")
+        .arg("-c")
+        .arg("2")
+        .arg("--")
+        .arg("echo")
+        .arg("secondary")
+        .assert()
+        .success()
+        .stdout(predicate::eq("secondary\n"))
+        .stderr(predicate::str::contains(
+            "cx: 'cat cx-non-existent-file' failed, falling back to 'echo secondary'",
+        ))
+        .stderr(predicate::str::contains(
+            "cx: alternative 2 of 2 succeeded: 'echo secondary'",
+        ));
+}
+
+#[test]
+fn retry_each_alternative_before_falling_back() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("fallback")
+        .arg("--count")
+        .arg("2")
+        .arg("--interval")
+        .arg("0")
+        .arg("--summary")
+        .arg("json")
+        .arg("--")
+        .arg("false")
+        .arg(":::")
+        .arg("-c")
+        .arg("3")
+        .arg("false")
+        .assert()
+        .code(1)
+        .stderr(predicate::function(|stderr: &str| {
+            let summary = stderr.lines().last().unwrap();
+            let summary = serde_json::from_str::<serde_json::Value>(summary).unwrap();
+            let numbers = summary["attempts"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|attempt| attempt["attempt"].as_u64().unwrap())
+                .collect::<Vec<_>>();
+            numbers == [1, 2, 3, 4, 5]
+        }));
+}
+
+#[test]
+fn frame_last_output_with_its_own_attempt() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("fallback")
+        .arg("--interval")
+        .arg("0")
+        .arg("--tail-lines")
+        .arg("1")
+        .arg("--")
+        .arg("ls")
+        .arg("cx-non-existent-file")
+        .arg(":::")
+        .arg("cx-non-existent-command")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "cx: ---- last attempt output (attempt 1: exited with code ",
+        ));
+}
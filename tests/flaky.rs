@@ -0,0 +1,58 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+#[test]
+fn report_pass_rate_of_passing_command() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("flaky")
+        .arg("-n")
+        .arg("3")
+        .arg("--")
+        .arg("true")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("passed 3 of 3 runs (100.0%"));
+}
+
+#[test]
+fn save_output_of_failing_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("flaky")
+        .arg("-n")
+        .arg("50")
+        .arg("--min-runs")
+        .arg("2")
+        .arg("--failures-dir")
+        .arg(dir.path())
+        .arg("--")
+        .arg("cat")
+        .arg("cx-missing-file")
+        .assert()
+        .code(1)
+        .stdout(
+            predicate::str::contains("passed 0 of 2 runs")
+                .and(predicate::str::contains("2 x exited with code 1")),
+        )
+        .stderr(predicate::str::contains("stopping after 2 of 50 runs"));
+
+    let stderr = std::fs::read_to_string(dir.path().join("attempt-2.stderr")).unwrap();
+    assert!(stderr.contains("cx-missing-file"));
+}
+
+#[test]
+fn pass_below_threshold() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("flaky")
+        .arg("-n")
+        .arg("2")
+        .arg("--threshold")
+        .arg("100%")
+        .arg("--")
+        .arg("false")
+        .assert()
+        .success();
+}
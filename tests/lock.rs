@@ -0,0 +1,83 @@
+#![cfg(unix)]
+
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+#[test]
+fn run_command_holding_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("job.lock");
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("lock")
+        .arg(&path)
+        .arg("--")
+        .arg("cat")
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""command":"cat "#));
+}
+
+#[test]
+fn exit_with_75_when_lock_busy() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("job.lock");
+    let mut holder = std::process::Command::new(env!("CARGO_BIN_EXE_cx"))
+        .arg("lock")
+        .arg(&path)
+        .arg("--")
+        .arg("sleep")
+        .arg("5")
+        .spawn()
+        .unwrap();
+    while !std::fs::read_to_string(&path).map_or(false, |text| text.contains("sleep 5")) {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+    cmd.arg("retry")
+        .arg("--lock")
+        .arg(&path)
+        .arg("--lock-wait")
+        .arg("100ms")
+        .arg("--")
+        .arg("true")
+        .assert()
+        .code(75)
+        .stderr(
+            predicate::str::contains("is held by pid").and(predicate::str::contains("'sleep 5'")),
+        );
+
+    holder.kill().unwrap();
+    holder.wait().unwrap();
+}
+
+#[test]
+fn release_lock_of_option_when_cx_exits() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("job.lock");
+    let script = dir.path().join("spawn.sh");
+    std::fs::write(&script, "#!/bin/sh\nsleep 3 > /dev/null 2>&1 &\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+    cmd.arg("retry")
+        .arg("--lock")
+        .arg(&path)
+        .arg("--")
+        .arg(&script)
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+    cmd.arg("lock")
+        .arg("--no-wait")
+        .arg(&path)
+        .arg("--")
+        .arg("true")
+        .assert()
+        .success();
+}
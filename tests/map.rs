@@ -0,0 +1,103 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+#[test]
+fn run_template_for_each_item() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("map")
+        .arg("-j")
+        .arg("2")
+        .arg("--")
+        .arg("echo")
+        .arg("item={}")
+        .write_stdin("a\n\nb\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[a] item=a\n"))
+        .stdout(predicate::str::contains("[b] item=b\n"));
+}
+
+#[test]
+fn resume_failed_items_from_results() {
+    let dir = tempfile::tempdir().unwrap();
+    let results = dir.path().join("results");
+    let present = dir.path().join("present");
+    std::fs::write(&present, "").unwrap();
+    let items = format!(
+        "{}\n{}\n",
+        present.display(),
+        dir.path().join("missing").display()
+    );
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+    cmd.arg("map")
+        .arg("--retry")
+        .arg("1")
+        .arg("--results")
+        .arg(&results)
+        .arg("--")
+        .arg("ls")
+        .write_stdin(items.clone())
+        .assert()
+        .code(1);
+
+    let text = std::fs::read_to_string(&results).unwrap();
+    assert!(text.contains(&format!("succeeded\t{}\n", present.display())));
+    assert!(text.contains("failed\t"));
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+    cmd.arg("map")
+        .arg("--results")
+        .arg(&results)
+        .arg("--resume")
+        .arg("--")
+        .arg("ls")
+        .write_stdin(items)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("present").not())
+        .stderr(predicate::str::contains(
+            "cx: skipping 1 items that already succeeded",
+        ));
+}
+
+#[test]
+fn run_failed_items_again() {
+    let dir = tempfile::tempdir().unwrap();
+    let failed = dir.path().join("failed");
+    let present = dir.path().join("present");
+    let missing = dir.path().join("missing");
+    std::fs::write(&present, "").unwrap();
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+    cmd.arg("map")
+        .arg("--failed")
+        .arg(&failed)
+        .arg("--")
+        .arg("ls")
+        .write_stdin(format!("{}\n{}\n", present.display(), missing.display()))
+        .assert()
+        .code(1);
+
+    assert_eq!(
+        std::fs::read_to_string(&failed).unwrap(),
+        format!("{}\n", missing.display())
+    );
+
+    std::fs::write(&missing, "").unwrap();
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+    cmd.arg("map")
+        .arg("-a")
+        .arg(&failed)
+        .arg("--failed")
+        .arg(&failed)
+        .arg("--")
+        .arg("ls")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("present").not());
+
+    assert_eq!(std::fs::read_to_string(&failed).unwrap(), "");
+}
@@ -0,0 +1,71 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+#[test]
+fn prefix_output_with_job_name() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("parallel")
+        .arg("-j")
+        .arg("2")
+        .arg("--")
+        .arg("echo")
+        .arg("a")
+        .arg(":::")
+        .arg("echo")
+        .arg("b")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[echo a] a\n"))
+        .stdout(predicate::str::contains("[echo b] b\n"));
+}
+
+#[test]
+fn read_commands_from_stdin() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("parallel")
+        .write_stdin("echo a\n\necho b\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[echo a] a\n"))
+        .stdout(predicate::str::contains("[echo b] b\n"));
+}
+
+#[test]
+fn halt_now_on_failure() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("parallel")
+        .arg("--halt")
+        .arg("now,fail=1")
+        .arg("--")
+        .arg("ls")
+        .arg("cx-non-existent-file")
+        .arg(":::")
+        .arg("sleep")
+        .arg("10")
+        .timeout(std::time::Duration::from_secs(5))
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "cx: halting with 0 succeeded and 1 failed jobs\n",
+        ));
+}
+
+#[test]
+fn prefix_spawn_errors_with_job_name() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("parallel")
+        .arg("--")
+        .arg("cx-non-existent-command")
+        .arg(":::")
+        .arg("echo")
+        .arg("b")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "cx: [cx-non-existent-command] command not found 'cx-non-existent-command'\n",
+        ));
+}
@@ -0,0 +1,126 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+#[test]
+fn take_first_success_and_kill_the_rest() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("race")
+        .arg("--")
+        .arg("sleep")
+        .arg("30")
+        .arg(":::")
+        .arg("echo")
+        .arg("fast")
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .success()
+        .stdout(predicate::eq("fast\n"))
+        .stderr(predicate::str::contains(
+            "'echo fast' won, killing 1 other alternative",
+        ));
+}
+
+#[test]
+fn show_only_winner_output() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("race")
+        .arg("--")
+        .arg("ls")
+        .arg("cx-non-existent-file")
+        .arg(":::")
+        .arg("echo")
+        .arg("winner")
+        .assert()
+        .success()
+        .stdout(predicate::eq("winner\n"))
+        .stderr(predicate::str::contains("cx-non-existent-file").not());
+}
+
+#[test]
+fn prefix_every_output() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("race")
+        .arg("--all-output")
+        .arg("--")
+        .arg("echo")
+        .arg("a")
+        .arg(":::")
+        .arg("echo")
+        .arg("b")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("[echo a] a\n").or(predicate::str::contains("[echo b] b\n")),
+        );
+}
+
+#[test]
+fn exit_with_best_failure() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("race")
+        .arg("--")
+        .arg("cx-missing-command")
+        .arg(":::")
+        .arg("false")
+        .assert()
+        .code(1)
+        .stderr(predicate::str::contains(
+            "command not found 'cx-missing-command'",
+        ));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn kill_alternatives_when_terminated() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("spawn.sh");
+    std::fs::write(&script, "#!/bin/sh\nsleep 30 &\necho $! > $1\nwait\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let pid_files = [dir.path().join("a"), dir.path().join("b")];
+    let mut cx = std::process::Command::new(assert_cmd::cargo::cargo_bin("cx"))
+        .arg("race")
+        .arg("--")
+        .arg(&script)
+        .arg(&pid_files[0])
+        .arg(":::")
+        .arg(&script)
+        .arg(&pid_files[1])
+        .spawn()
+        .unwrap();
+
+    let read_pid = |path: &std::path::Path| {
+        for _ in 0..500 {
+            match std::fs::read_to_string(path) {
+                Ok(pid) if pid.ends_with('\n') => return pid.trim().to_string(),
+                _ => std::thread::sleep(std::time::Duration::from_millis(10)),
+            }
+        }
+        panic!("{} was not written", path.display());
+    };
+    let pids = pid_files
+        .iter()
+        .map(|path| read_pid(path))
+        .collect::<Vec<_>>();
+
+    unsafe { libc::kill(cx.id() as libc::pid_t, libc::SIGTERM) };
+    assert_eq!(cx.wait().unwrap().code(), Some(128 + libc::SIGTERM));
+
+    let alive = |pid: &String| match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        Ok(stat) => !stat.contains(") Z "),
+        Err(_) => false,
+    };
+    for _ in 0..100 {
+        if !pids.iter().any(alive) {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(!pids.iter().any(alive));
+}
@@ -54,3 +54,365 @@ fn sleep_one_time() {
 
     assert!(now.elapsed() >= std::time::Duration::from_secs_f64(0.5))
 }
+
+#[cfg(unix)]
+#[test]
+fn run_on_pseudo_terminal() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("-c")
+        .arg("1")
+        .arg("--pty")
+        .arg("--")
+        .arg("tty")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("/dev/"));
+}
+
+#[cfg(unix)]
+#[test]
+fn kill_on_pseudo_terminal_beyond_output_limit() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("-c")
+        .arg("1")
+        .arg("--pty")
+        .arg("--max-output-bytes")
+        .arg("100")
+        .arg("--output-limit-policy")
+        .arg("kill")
+        .arg("--")
+        .arg("echo")
+        .arg("hi")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("hi"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn give_up_when_cpu_limit_exceeded() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("--limit-cpu")
+        .arg("1")
+        .arg("--")
+        .arg("sha256sum /dev/zero")
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .failure();
+}
+
+#[cfg(unix)]
+#[test]
+fn run_as_another_user() {
+    let uid = std::process::Command::new("id").arg("-u").output().unwrap();
+    if uid.stdout != b"0\n" {
+        return;
+    }
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("-c")
+        .arg("1")
+        .arg("--user")
+        .arg("nobody")
+        .arg("--")
+        .arg("id -un")
+        .assert()
+        .success()
+        .stdout(predicate::eq("nobody\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn fail_early_given_unknown_user() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("--user")
+        .arg("cx-unknown-user")
+        .arg("--")
+        .arg("echo abc")
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::starts_with("cx: "));
+}
+
+#[cfg(unix)]
+#[test]
+fn report_signal_termination() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("-c")
+        .arg("1")
+        .arg("--limit-core")
+        .arg("0")
+        .arg("--")
+        .arg("sh -c kill$IFS-SEGV$IFS$$")
+        .assert()
+        .failure()
+        .stderr(predicate::eq("cx: 'sh' killed by SIGSEGV\n"));
+}
+
+#[test]
+fn show_final_output_only() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("-c")
+        .arg("3")
+        .arg("--output")
+        .arg("final")
+        .arg("--")
+        .arg("ls cx-non-existent-file")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cx-non-existent-file").count(1));
+}
+
+#[test]
+fn write_lifecycle_events() {
+    let path = std::env::temp_dir().join(format!("cx-retry-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("-c")
+        .arg("2")
+        .arg("-i")
+        .arg("0")
+        .arg("--events")
+        .arg(format!("json:{}", path.display()))
+        .arg("--")
+        .arg("ls cx-non-existent-file")
+        .assert()
+        .failure();
+
+    let events = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    let names = events
+        .iter()
+        .map(|event| event["event"].as_str().unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        names,
+        [
+            "attempt_started",
+            "attempt_exited",
+            "sleeping",
+            "attempt_started",
+            "attempt_exited",
+            "gave_up"
+        ]
+    );
+    assert!(events.iter().all(|event| event["version"] == 1));
+    assert!(events[3]["pid"].is_u64());
+    assert_eq!(events[4]["attempt"], 2);
+    assert_eq!(events[5]["attempts"], 2);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn print_json_summary() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    let assert = cmd
+        .arg("retry")
+        .arg("-c")
+        .arg("2")
+        .arg("-i")
+        .arg("0")
+        .arg("--summary")
+        .arg("json")
+        .arg("--")
+        .arg("ls cx-non-existent-file")
+        .assert()
+        .failure();
+
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    let summary =
+        serde_json::from_str::<serde_json::Value>(stderr.lines().last().unwrap()).unwrap();
+
+    assert_eq!(summary["attempts"].as_array().unwrap().len(), 2);
+    assert_eq!(summary["attempts"][1]["attempt"], 2);
+    assert_ne!(summary["attempts"][1]["code"], 0);
+    assert!(summary["total_sleep_ms"].is_u64());
+}
+
+#[test]
+fn truncate_output_beyond_limit() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("--max-output-bytes")
+        .arg("4")
+        .arg("--")
+        .arg("echo abcdefgh")
+        .assert()
+        .success()
+        .stdout(predicate::eq(
+            "abcd\n[cx: output truncated after 4 bytes]\n",
+        ));
+}
+
+#[cfg(unix)]
+#[test]
+fn kill_what_the_command_started_beyond_output_limit() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("spawn.sh");
+    std::fs::write(&script, "#!/bin/sh\nyes &\nwait\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("-c")
+        .arg("1")
+        .arg("--max-output-bytes")
+        .arg("100")
+        .arg("--output-limit-policy")
+        .arg("kill")
+        .arg("--")
+        .arg(&script)
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("[cx: "));
+}
+
+#[test]
+fn show_last_output_when_giving_up() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("-c")
+        .arg("2")
+        .arg("-i")
+        .arg("0")
+        .arg("--output")
+        .arg("none")
+        .arg("--tail-lines")
+        .arg("1")
+        .arg("--")
+        .arg("echo abc")
+        .assert()
+        .success()
+        .stderr(predicate::str::is_empty());
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("-c")
+        .arg("2")
+        .arg("-i")
+        .arg("0")
+        .arg("--output")
+        .arg("none")
+        .arg("--tail-lines")
+        .arg("1")
+        .arg("--")
+        .arg("ls cx-non-existent-file")
+        .assert()
+        .failure()
+        .stderr(predicate::str::starts_with(
+            "cx: ---- last attempt output (attempt 2: exited with code ",
+        ))
+        .stderr(predicate::str::ends_with(
+            "No such file or directory\ncx: ---- end of last attempt output ----\n",
+        ));
+}
+
+#[test]
+fn show_end_of_output_cut_off_by_limit() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("-c")
+        .arg("1")
+        .arg("--max-output-bytes")
+        .arg("5")
+        .arg("--tail-lines")
+        .arg("1")
+        .arg("--")
+        .arg("ls -d . cx-non-existent-file")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "[cx: output truncated after 5 bytes]",
+        ))
+        .stderr(predicate::str::ends_with(
+            "No such file or directory\ncx: ---- end of last attempt output ----\n",
+        ));
+}
+
+#[test]
+fn write_artifacts_of_spawn_failures() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("-c")
+        .arg("1")
+        .arg("--artifacts-dir")
+        .arg(dir.path())
+        .arg("--")
+        .arg("cx-non-existent-command")
+        .assert()
+        .failure();
+
+    let metadata = std::fs::read_to_string(dir.path().join("attempt-1.json")).unwrap();
+    let metadata = serde_json::from_str::<serde_json::Value>(&metadata).unwrap();
+
+    assert_eq!(metadata["command"], "cx-non-existent-command");
+    assert_eq!(metadata["code"], serde_json::Value::Null);
+    assert_eq!(
+        metadata["error"],
+        "command not found 'cx-non-existent-command'"
+    );
+    assert!(!dir.path().join("attempt-1.stdout").exists());
+}
+
+#[test]
+fn write_artifacts() {
+    let dir = std::env::temp_dir().join(format!("cx-retry-{}-artifacts", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("retry")
+        .arg("--artifacts-dir")
+        .arg(&dir)
+        .arg("--")
+        .arg("echo abc")
+        .assert()
+        .success()
+        .stdout(predicate::eq("abc\n"));
+
+    let stdout = std::fs::read_to_string(dir.join("attempt-1.stdout")).unwrap();
+    let metadata = std::fs::read_to_string(dir.join("attempt-1.json")).unwrap();
+    let metadata = serde_json::from_str::<serde_json::Value>(&metadata).unwrap();
+
+    assert_eq!(stdout, "abc\n");
+    assert_eq!(
+        std::fs::read_to_string(dir.join("attempt-1.stderr")).unwrap(),
+        ""
+    );
+    assert_eq!(metadata["code"], 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
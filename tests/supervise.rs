@@ -33,3 +33,134 @@ fn sleep_one_time() {
 
     assert!(now.elapsed() >= std::time::Duration::from_secs_f64(0.5))
 }
+
+#[test]
+fn prefix_attempt_and_stream() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("supervise")
+        .arg("-c")
+        .arg("2")
+        .arg("--prefix")
+        .arg("[#{attempt} {stream}] ")
+        .arg("--")
+        .arg("echo abc")
+        .assert()
+        .success()
+        .stdout(predicate::eq("[#1 stdout] abc\n[#2 stdout] abc\n"));
+}
+
+#[test]
+fn write_log_file() {
+    let path = std::env::temp_dir().join(format!("cx-supervise-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("supervise")
+        .arg("-c")
+        .arg("2")
+        .arg("--log-file")
+        .arg(&path)
+        .arg("--")
+        .arg("echo abc")
+        .assert()
+        .success()
+        .stdout(predicate::eq("abc\nabc\n"));
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "abc\nabc\n");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn send_output_to_journal() {
+    use std::os::unix::net::UnixDatagram;
+
+    let path = std::env::temp_dir().join(format!("cx-supervise-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let socket = UnixDatagram::bind(&path).unwrap();
+    socket.set_nonblocking(true).unwrap();
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("supervise")
+        .arg("-c")
+        .arg("2")
+        .arg("--log-to")
+        .arg("journald")
+        .arg("--log-socket")
+        .arg(&path)
+        .arg("--")
+        .arg("echo abc")
+        .assert()
+        .success();
+
+    let mut buf = [0u8; 4096];
+    let mut messages = Vec::new();
+    while let Ok(len) = socket.recv(&mut buf) {
+        messages.push(String::from_utf8_lossy(&buf[..len]).into_owned());
+    }
+
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0].contains("SYSLOG_IDENTIFIER=echo\n"));
+    assert!(messages[0].contains("PRIORITY=6\n"));
+    assert!(messages[1].contains("CX_ATTEMPT=2\n"));
+    assert!(messages[1].ends_with("MESSAGE=abc\n"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn collapse_repeated_output() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("supervise")
+        .arg("-c")
+        .arg("3")
+        .arg("--collapse-repeats")
+        .arg("--")
+        .arg("echo abc")
+        .assert()
+        .success()
+        .stdout(predicate::eq("abc\n"))
+        .stderr(predicate::eq(
+            "cx: attempt 2 output identical to attempt 1 (1 lines suppressed)\n\
+             cx: attempt 3 output identical to attempt 2 (1 lines suppressed)\n",
+        ));
+}
+
+#[test]
+fn write_events_of_spawn_failures() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("events.jsonl");
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("supervise")
+        .arg("-c")
+        .arg("1")
+        .arg("--events")
+        .arg(format!("json:{}", path.display()))
+        .arg("--")
+        .arg("cx-non-existent-command")
+        .assert()
+        .success();
+
+    let events = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0]["event"], "attempt_started");
+    assert_eq!(events[0]["pid"], serde_json::Value::Null);
+    assert_eq!(events[1]["event"], "attempt_exited");
+    assert_eq!(events[1]["code"], serde_json::Value::Null);
+    assert_eq!(
+        events[1]["error"],
+        "command not found 'cx-non-existent-command'"
+    );
+    assert_eq!(events[2]["event"], "stopped");
+    assert_eq!(events[2]["attempts"], 1);
+}
@@ -0,0 +1,119 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+#[test]
+fn exit_with_status_of_finished_command() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("timeout")
+        .arg("5s")
+        .arg("--")
+        .arg("echo")
+        .arg("abcd")
+        .assert()
+        .success()
+        .stdout(predicate::eq("abcd\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn exit_with_124_on_timeout() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("timeout")
+        .arg("0.1")
+        .arg("--")
+        .arg("sleep")
+        .arg("5")
+        .assert()
+        .code(124);
+}
+
+#[cfg(unix)]
+#[test]
+fn preserve_status_of_killed_command() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("timeout")
+        .arg("100ms")
+        .arg("--signal")
+        .arg("CONT")
+        .arg("--kill-after")
+        .arg("100ms")
+        .arg("--preserve-status")
+        .arg("--")
+        .arg("sleep")
+        .arg("5")
+        .assert()
+        .code(137);
+}
+
+#[cfg(unix)]
+#[test]
+fn continue_stopped_command_on_timeout() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("timeout")
+        .arg("0.1")
+        .arg("--")
+        .arg("sh")
+        .arg("-c")
+        .arg("kill$IFS-STOP$IFS$$")
+        .timeout(std::time::Duration::from_secs(5))
+        .assert()
+        .code(124);
+}
+
+#[cfg(unix)]
+#[test]
+fn run_command_in_foreground() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("timeout")
+        .arg("--foreground")
+        .arg("5s")
+        .arg("--")
+        .arg("sh")
+        .arg("-c")
+        .arg("ps$IFS-o$IFS'pgid='$IFS-p$IFS$$$IFS$PPID")
+        .assert()
+        .success()
+        .stdout(predicate::function(|stdout: &str| {
+            let groups = stdout.split_whitespace().collect::<Vec<_>>();
+            groups.len() == 2 && groups[0] == groups[1]
+        }));
+}
+
+#[test]
+fn exit_with_127_when_command_not_found() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("timeout")
+        .arg("5s")
+        .arg("--")
+        .arg("cx-missing-command")
+        .assert()
+        .code(127)
+        .stderr(predicate::str::contains("command not found"));
+}
+
+#[cfg(unix)]
+#[test]
+fn signal_what_the_command_started() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("spawn.sh");
+    std::fs::write(&script, "#!/bin/sh\nsleep 30 &\nwait\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("timeout")
+        .arg("0.2")
+        .arg("--")
+        .arg(&script)
+        .timeout(std::time::Duration::from_secs(10))
+        .assert()
+        .code(124);
+}
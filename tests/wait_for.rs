@@ -0,0 +1,113 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+#[cfg(unix)]
+#[test]
+fn run_command_once_ready() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("wait-for")
+        .arg(format!("file:{}", dir.path().display()))
+        .arg("cmd:true")
+        .arg("--")
+        .arg("echo")
+        .arg("ready")
+        .assert()
+        .success()
+        .stdout(predicate::eq("ready\n"));
+}
+
+#[test]
+fn exit_with_124_on_timeout() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("wait-for")
+        .arg("file:/cx-non-existent-file")
+        .arg("--interval")
+        .arg("0.05")
+        .arg("--timeout")
+        .arg("200ms")
+        .arg("--")
+        .arg("echo")
+        .arg("ready")
+        .assert()
+        .code(124)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::eq(
+            "cx: timed out waiting for file:/cx-non-existent-file\n",
+        ));
+}
+
+#[cfg(unix)]
+#[test]
+fn exit_with_127_when_command_not_found() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("wait-for")
+        .arg("cmd:true")
+        .arg("--")
+        .arg("cx-missing-command")
+        .assert()
+        .code(127)
+        .stderr(predicate::str::contains(
+            "command not found 'cx-missing-command'",
+        ));
+}
+
+#[test]
+fn fail_when_probe_cannot_be_spawned() {
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("wait-for")
+        .arg("cmd:cx-non-existent-command")
+        .arg("--timeout")
+        .arg("10s")
+        .timeout(std::time::Duration::from_secs(5))
+        .assert()
+        .code(127)
+        .stderr(predicate::eq(
+            "cx: command not found 'cx-non-existent-command'\n",
+        ));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn kill_probe_cut_off_by_timeout() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let script = dir.path().join("probe.sh");
+    let pid_file = dir.path().join("pid");
+    std::fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\nsleep 30 &\necho $! > {}\nwait\n",
+            pid_file.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut cmd = Command::cargo_bin("cx").unwrap();
+
+    cmd.arg("wait-for")
+        .arg(format!("cmd:{}", script.display()))
+        .arg("--timeout")
+        .arg("1s")
+        .assert()
+        .code(124);
+
+    let pid = std::fs::read_to_string(&pid_file).unwrap();
+    let alive = || match std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())) {
+        Ok(stat) => !stat.contains(") Z "),
+        Err(_) => false,
+    };
+    for _ in 0..100 {
+        if !alive() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(!alive());
+}